@@ -1,68 +1,552 @@
-use crate::models::query_params::QueryParams;
-use bson::{doc, Document};
-use futures::stream::TryStreamExt;
-use mongodb::{error::Error as MongoError, Collection, Database};
+use crate::error::ServiceError;
+use crate::models::{
+    collection_type::CollectionType,
+    field_registry::{find_mismatch, FieldRegistry},
+    page::Page,
+    query_params::{FieldFilter, QueryParams},
+};
+use crate::utils::cursor::PageCursor;
+use crate::utils::date_utils::RangeMode;
+use bson::{doc, Bson, Document, RawDocumentBuf};
+use futures::future;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use mongodb::{error::Error as MongoError, options::UpdateOptions, Collection, Database};
 use serde::de::DeserializeOwned;
 
 pub struct BaseDB {
+    collection_type: CollectionType,
     collection: Collection<Document>,
 }
 
 impl BaseDB {
     const DEFAULT_LIMIT: i64 = 24;
-    const MAX_RECORDS_NO_FILTER: i64 = 400;
+    pub const MAX_RECORDS_NO_FILTER: i64 = 400;
 
-    pub fn new(db: &Database, collection_name: &str) -> Self {
+    pub fn new(db: &Database, collection_type: CollectionType) -> Self {
         Self {
-            collection: db.collection(collection_name),
+            collection_type,
+            collection: db.collection(collection_type.as_str()),
         }
     }
 
-    pub async fn find_documents<T>(&self, params: &QueryParams) -> Result<Vec<T>, MongoError>
+    /// Stages selecting and grouping the collection's stored documents.
+    fn build_pipeline(&self, params: &QueryParams) -> Vec<Document> {
+        let mut pipeline = Vec::new();
+
+        // Without a pool, collections that also store per-pool series return
+        // only their aggregate series, which has no `pool` field.
+        let pool = match &params.pool {
+            Some(pool) => Some(Bson::String(pool.clone())),
+            None if self.collection_type.is_per_pool()
+                && self.collection_type.has_aggregate_series() =>
+            {
+                Some(Bson::Null)
+            }
+            None => None,
+        };
+
+        let (stored_filters, grouped_filters) = split_filters(params);
+        if let Some(match_stage) = crate::utils::build_match_stage(
+            params.time_range(),
+            params.range_mode(),
+            pool,
+            stored_filters,
+        ) {
+            pipeline.push(match_stage);
+        }
+
+        pipeline.extend(crate::utils::build_group_stages(
+            &params.interval,
+            self.collection_type.fields(),
+            self.collection_type.is_per_pool(),
+        ));
+        pipeline.extend(crate::utils::build_match_stage(
+            None,
+            RangeMode::default(),
+            None,
+            grouped_filters,
+        ));
+
+        pipeline
+    }
+
+    /// Stages producing one row per element of the `array_field` array, with
+    /// the parent interval's `startTime` and `endTime` merged into it. Rows
+    /// are restricted to the given pools when any are given, and grouped per
+    /// pool by the aggregations of `row_fields` when an interval is requested.
+    fn build_unwound_pipeline(
+        &self,
+        params: &QueryParams,
+        array_field: &str,
+        pools: &[String],
+        row_fields: FieldRegistry,
+    ) -> Vec<Document> {
+        let mut pipeline = Vec::new();
+
+        if let Some(match_stage) =
+            crate::utils::build_match_stage(params.time_range(), params.range_mode(), None, &[])
+        {
+            pipeline.push(match_stage);
+        }
+
+        let array_path = format!("${}", array_field);
+        let pool_field = format!("{}.pool", array_field);
+        pipeline.push(doc! { "$unwind": &array_path });
+
+        if !pools.is_empty() {
+            pipeline.push(doc! { "$match": { &pool_field: { "$in": pools } } });
+        }
+
+        pipeline.push(doc! {
+            "$replaceRoot": {
+                "newRoot": {
+                    "$mergeObjects": [
+                        &array_path,
+                        {
+                            "_id": { "interval": "$_id", "pool": format!("${}", pool_field) },
+                            "startTime": "$startTime",
+                            "endTime": "$endTime"
+                        }
+                    ]
+                }
+            }
+        });
+
+        // Filters apply to the pool rows, which only exist after unwinding.
+        let (stored_filters, grouped_filters) = split_filters(params);
+        if let Some(match_stage) =
+            crate::utils::build_match_stage(None, RangeMode::default(), None, stored_filters)
+        {
+            pipeline.push(match_stage);
+        }
+
+        pipeline.extend(crate::utils::build_group_stages(
+            &params.interval,
+            row_fields,
+            true,
+        ));
+        pipeline.extend(crate::utils::build_match_stage(
+            None,
+            RangeMode::default(),
+            None,
+            grouped_filters,
+        ));
+
+        pipeline
+    }
+
+    /// One page of stored documents, to be read with `read_page`.
+    pub async fn find_documents(
+        &self,
+        params: &QueryParams,
+    ) -> Result<Page<RawDocumentBuf>, ServiceError> {
+        let pipeline = self.build_pipeline(params);
+        self.execute_page(pipeline, params).await
+    }
+
+    /// Every matching document, read from the database as the stream is
+    /// consumed.
+    pub async fn stream_documents<T>(
+        &self,
+        params: &QueryParams,
+    ) -> Result<BoxStream<'static, Result<T, ServiceError>>, ServiceError>
     where
-        T: DeserializeOwned,
+        T: DeserializeOwned + Send + 'static,
     {
         let pipeline = self.build_pipeline(params);
-        self.execute_pipeline(pipeline).await
+        self.execute_stream(pipeline, params, self.collection_type.fields())
+            .await
     }
 
-    fn build_pipeline(&self, params: &QueryParams) -> Vec<Document> {
-        if params.date_range.is_none() && params.sort_by.is_none() {
-            return vec![doc! { "$limit": Self::MAX_RECORDS_NO_FILTER }];
+    /// Returns one row per element of the `array_field` array, see
+    /// `build_unwound_pipeline`.
+    pub async fn find_unwound_documents(
+        &self,
+        params: &QueryParams,
+        array_field: &str,
+        pools: &[String],
+        row_fields: FieldRegistry,
+    ) -> Result<Page<RawDocumentBuf>, ServiceError> {
+        let pipeline = self.build_unwound_pipeline(params, array_field, pools, row_fields);
+        self.execute_page(pipeline, params).await
+    }
+
+    /// Streaming counterpart of `find_unwound_documents`.
+    pub async fn stream_unwound_documents<T>(
+        &self,
+        params: &QueryParams,
+        array_field: &str,
+        pools: &[String],
+        row_fields: FieldRegistry,
+    ) -> Result<BoxStream<'static, Result<T, ServiceError>>, ServiceError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let pipeline = self.build_unwound_pipeline(params, array_field, pools, row_fields);
+        self.execute_stream(pipeline, params, row_fields).await
+    }
+
+    /// Number of results per page: `limit` when given, capped at
+    /// `MAX_RECORDS_NO_FILTER`. Without it, queries with neither a time range
+    /// nor a sort return the full `MAX_RECORDS_NO_FILTER`, others
+    /// `DEFAULT_LIMIT`.
+    fn page_limit(params: &QueryParams) -> i64 {
+        let default = if !params.has_time_range() && params.sort_by.is_none() {
+            Self::MAX_RECORDS_NO_FILTER
+        } else {
+            Self::DEFAULT_LIMIT
+        };
+
+        params
+            .limit
+            .unwrap_or(default)
+            .min(Self::MAX_RECORDS_NO_FILTER)
+    }
+
+    /// Appends the cursor, sort, limit and projection stages to `pipeline`.
+    fn append_read_stages(
+        pipeline: &mut Vec<Document>,
+        params: &QueryParams,
+        sort_keys: &[(String, i32)],
+        limit: Option<i64>,
+    ) -> Result<(), ServiceError> {
+        if let Some(token) = &params.cursor {
+            let cursor = PageCursor::decode(token, sort_keys).map_err(ServiceError::Validation)?;
+            pipeline.push(cursor.match_stage());
         }
 
-        let mut pipeline = Vec::new();
+        pipeline.push(crate::utils::build_sort_stage(
+            &params.sort_by,
+            &params.order,
+        ));
 
-        if let Some(match_stage) = crate::utils::build_match_stage(&params.date_range) {
-            pipeline.push(match_stage);
+        if let Some(limit) = limit {
+            pipeline.push(doc! { "$limit": limit });
         }
 
-        if let Some(sort_stage) = crate::utils::build_sort_stage(&params.sort_by, &params.order) {
-            pipeline.push(sort_stage);
+        // Sort keys stay in the projection so the next cursor can be built.
+        if let Some(selected) = params.selected_fields() {
+            let projection: Document = selected
+                .into_iter()
+                .chain(sort_keys.iter().map(|(field, _)| field.as_str()))
+                .map(|field| (field.to_string(), Bson::Int32(1)))
+                .collect();
+            pipeline.push(doc! { "$project": projection });
         }
 
-        let limit = params
-            .limit
-            .unwrap_or(Self::DEFAULT_LIMIT)
-            .min(Self::MAX_RECORDS_NO_FILTER) as i64;
-        pipeline.push(doc! { "$limit": limit });
+        Ok(())
+    }
 
-        pipeline
+    /// Reads a stored document as `T`. Documents with a field of `fields`
+    /// stored as another type, or that do not deserialize, are skipped and
+    /// `None` is returned; when `report` is set they are also logged with
+    /// their `_id` and the offending field and counted in the metrics. The
+    /// registry is checked whatever `T` is, so documents read as a plain
+    /// `Document` are held to the model too.
+    fn read_document<T>(
+        collection_type: CollectionType,
+        raw: &RawDocumentBuf,
+        fields: FieldRegistry,
+        report: bool,
+    ) -> Option<T>
+    where
+        T: DeserializeOwned,
+    {
+        let document = raw.to_document().unwrap_or_default();
+        let error = match find_mismatch(&document, fields) {
+            Some(field) => format!("field {} does not have the declared type", field),
+            None => match bson::from_slice::<T>(raw.as_bytes()) {
+                Ok(item) => return Some(item),
+                Err(error) => error.to_string(),
+            },
+        };
+
+        if !report {
+            return None;
+        }
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        log::warn!(
+            "Skipping {} document {} ({})",
+            collection_type.as_str(),
+            id,
+            error
+        );
+        crate::utils::metrics::record_deserialization_failures(collection_type, 1);
+
+        None
     }
 
-    async fn execute_pipeline<T>(&self, pipeline: Vec<Document>) -> Result<Vec<T>, MongoError>
+    /// Runs `pipeline` for one page. One document beyond the limit is read
+    /// to tell whether a next page exists.
+    async fn execute_page(
+        &self,
+        mut pipeline: Vec<Document>,
+        params: &QueryParams,
+    ) -> Result<Page<RawDocumentBuf>, ServiceError> {
+        let sort_keys = crate::utils::sort_keys(&params.sort_by, &params.order);
+        let limit = Self::page_limit(params);
+        Self::append_read_stages(&mut pipeline, params, &sort_keys, Some(limit + 1))?;
+
+        let mut documents: Vec<RawDocumentBuf> = self
+            .collection
+            .aggregate(pipeline, None)
+            .await?
+            .with_type::<RawDocumentBuf>()
+            .try_collect()
+            .await?;
+
+        let next = if documents.len() as i64 > limit {
+            documents.truncate(limit as usize);
+            documents
+                .last()
+                .and_then(|last| last.to_document().ok())
+                .map(|last| PageCursor::after(&sort_keys, &last).encode())
+        } else {
+            None
+        };
+
+        Ok(Page {
+            items: documents,
+            next,
+            limit,
+            skipped: 0,
+        })
+    }
+
+    /// Reads a page of stored documents as `T`, typed by `fields`. Documents
+    /// that cannot be read are skipped, or fail the request in strict mode.
+    /// They are reported only when `fresh`, so a page served again from a
+    /// cache does not count the same documents twice.
+    pub fn read_page<T>(
+        &self,
+        page: &Page<RawDocumentBuf>,
+        params: &QueryParams,
+        fields: FieldRegistry,
+        fresh: bool,
+    ) -> Result<Page<T>, ServiceError>
     where
         T: DeserializeOwned,
     {
-        let mut cursor = self.collection.aggregate(pipeline, None).await?;
-        let mut results = Vec::new();
+        let read = page.items.len();
+        let items: Vec<T> = page
+            .items
+            .iter()
+            .filter_map(|document| {
+                Self::read_document(self.collection_type, document, fields, fresh)
+            })
+            .collect();
+        let skipped = (read - items.len()) as u64;
 
-        while let Some(doc_result) = cursor.try_next().await? {
-            if let Ok(item) = bson::from_document(doc_result) {
-                results.push(item);
-            }
+        if skipped > 0 && params.strict == Some(true) {
+            return Err(ServiceError::DataIntegrity(format!(
+                "{} of {} {} documents could not be read",
+                skipped,
+                read,
+                self.collection_type.as_str()
+            )));
+        }
+
+        Ok(Page {
+            items,
+            next: page.next.clone(),
+            limit: page.limit,
+            skipped,
+        })
+    }
+
+    /// Runs `pipeline` without paging, yielding documents as the database
+    /// returns them. `limit`, when given, caps the number of documents read.
+    /// In strict mode, a document that cannot be read ends the stream with an
+    /// error.
+    async fn execute_stream<T>(
+        &self,
+        mut pipeline: Vec<Document>,
+        params: &QueryParams,
+        fields: FieldRegistry,
+    ) -> Result<BoxStream<'static, Result<T, ServiceError>>, ServiceError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let sort_keys = crate::utils::sort_keys(&params.sort_by, &params.order);
+        Self::append_read_stages(&mut pipeline, params, &sort_keys, params.limit)?;
+
+        let cursor = self
+            .collection
+            .aggregate(pipeline, None)
+            .await?
+            .with_type::<RawDocumentBuf>();
+
+        let collection_type = self.collection_type;
+        let strict = params.strict == Some(true);
+        let items = cursor
+            .map_err(ServiceError::from)
+            .try_filter_map(move |document| {
+                let item = match Self::read_document(collection_type, &document, fields, true) {
+                    Some(item) => Ok(Some(item)),
+                    None if strict => Err(ServiceError::DataIntegrity(format!(
+                        "a {} document could not be read",
+                        collection_type.as_str()
+                    ))),
+                    None => Ok(None),
+                };
+                future::ready(item)
+            });
+
+        Ok(items.boxed())
+    }
+
+    /// Upserts each document, matching existing ones on `key_fields`. A key
+    /// field missing from the document matches stored documents without it.
+    /// Returns the number of documents inserted or changed.
+    pub async fn upsert_documents(
+        &self,
+        documents: Vec<Document>,
+        key_fields: &[&str],
+    ) -> Result<u64, MongoError> {
+        let options = UpdateOptions::builder().upsert(true).build();
+        let mut written = 0;
+
+        for document in documents {
+            let filter: Document = key_fields
+                .iter()
+                .map(|field| {
+                    let value = document.get(*field).cloned().unwrap_or(Bson::Null);
+                    (field.to_string(), value)
+                })
+                .collect();
+
+            let result = self
+                .collection
+                .update_one(filter, doc! { "$set": document }, options.clone())
+                .await?;
+            written += result.modified_count + u64::from(result.upserted_id.is_some());
+        }
+
+        Ok(written)
+    }
+}
+
+/// Splits the value filters into those applied to the stored intervals and
+/// those applied after grouping. With `interval`, filters select the
+/// grouped buckets rather than the intervals they are built from.
+fn split_filters(params: &QueryParams) -> (&[FieldFilter], &[FieldFilter]) {
+    if params.interval.is_some() {
+        (&[], &params.filters)
+    } else {
+        (&params.filters, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::swaps_model::Swaps;
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    fn volume_filter() -> FieldFilter {
+        FieldFilter {
+            field: "totalVolume".to_string(),
+            operator: "gte".to_string(),
+            value: "100".to_string(),
         }
+    }
+
+    async fn swaps_db() -> BaseDB {
+        // The client connects lazily, so no server is needed to build stages.
+        let client = mongodb::Client::with_uri_str("mongodb://127.0.0.1:1")
+            .await
+            .unwrap();
+        BaseDB::new(&client.database("test"), CollectionType::Swaps)
+    }
+
+    #[actix_web::test]
+    async fn filters_apply_to_grouped_buckets() {
+        let db = swaps_db().await;
+        let filter = doc! { "$match": { "totalVolume": { "$gte": 100_i64 } } };
+
+        let stored = QueryParams {
+            filters: vec![volume_filter()],
+            ..Default::default()
+        };
+        let pipeline = db.build_pipeline(&stored);
+        assert_eq!(
+            pipeline[0]
+                .get_document("$match")
+                .unwrap()
+                .get("totalVolume"),
+            filter.get_document("$match").unwrap().get("totalVolume")
+        );
+        assert_eq!(pipeline.len(), 1);
+
+        let grouped = QueryParams {
+            interval: text("day"),
+            filters: vec![volume_filter()],
+            ..Default::default()
+        };
+        let pipeline = db.build_pipeline(&grouped);
+        assert!(!pipeline[0]
+            .get_document("$match")
+            .unwrap()
+            .contains_key("totalVolume"));
+        assert!(pipeline[pipeline.len() - 2].contains_key("$set"));
+        assert_eq!(pipeline.last(), Some(&filter));
+
+        let pools = db.build_unwound_pipeline(&grouped, "pools", &[], Swaps::FIELDS);
+        assert_eq!(pools.last(), Some(&filter));
+    }
+
+    #[test]
+    fn projections_keep_the_sort_keys_for_the_next_cursor() {
+        let params = QueryParams {
+            sort_by: text("-totalVolume"),
+            fields: text("totalVolumeUSD,startTime"),
+            ..Default::default()
+        };
+        let sort_keys = crate::utils::sort_keys(&params.sort_by, &params.order);
+        let mut pipeline = Vec::new();
+        BaseDB::append_read_stages(&mut pipeline, &params, &sort_keys, Some(25)).unwrap();
+
+        assert_eq!(
+            pipeline,
+            [
+                doc! { "$sort": { "totalVolume": -1, "_id": 1 } },
+                doc! { "$limit": 25_i64 },
+                doc! {
+                    "$project": {
+                        "totalVolumeUSD": 1,
+                        "startTime": 1,
+                        "totalVolume": 1,
+                        "_id": 1,
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cursors_resume_before_sorting() {
+        let sort_keys = crate::utils::sort_keys(&None, &None);
+        let last = doc! { "startTime": bson::DateTime::from_millis(0), "_id": 7 };
+        let params = QueryParams {
+            cursor: Some(PageCursor::after(&sort_keys, &last).encode()),
+            ..Default::default()
+        };
+        let mut pipeline = Vec::new();
+        BaseDB::append_read_stages(&mut pipeline, &params, &sort_keys, None).unwrap();
+
+        assert_eq!(pipeline.len(), 2);
+        assert!(pipeline[0].contains_key("$match"));
+        assert_eq!(pipeline[1], doc! { "$sort": { "startTime": 1, "_id": 1 } });
 
-        Ok(results)
+        let params = QueryParams {
+            cursor: text("not a cursor"),
+            ..Default::default()
+        };
+        let error =
+            BaseDB::append_read_stages(&mut Vec::new(), &params, &sort_keys, None).unwrap_err();
+        assert_eq!(error.code(), "VALIDATION_ERROR");
     }
 }
@@ -1,7 +1,232 @@
-use mongodb::{Client, Database};
+use crate::models::collection_type::CollectionType;
+use bson::{doc, Bson, Document};
+use futures::stream::TryStreamExt;
+use mongodb::{
+    error::{Error, ErrorKind, WriteFailure},
+    options::{AggregateOptions, IndexOptions},
+    Client, Collection, Database, IndexModel,
+};
+use thiserror::Error;
 pub mod base_db;
+pub mod sync_state_db;
 
-pub async fn init_db(uri: &str, db_name: &str) -> Result<Database, mongodb::error::Error> {
+const UNIQUE_INTERVAL_INDEX: &str = "unique_interval";
+
+/// Number of duplicate interval keys named in a `DuplicateIntervals` error.
+const REPORTED_DUPLICATES: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum InitError {
+    #[error(transparent)]
+    Mongo(#[from] Error),
+
+    #[error(
+        "{collection} holds {count} intervals stored more than once, e.g. {}; remove them or \
+         restart with REMOVE_DUPLICATE_INTERVALS=true to keep the latest copy of each",
+        format_keys(.keys)
+    )]
+    DuplicateIntervals {
+        collection: String,
+        count: usize,
+        keys: Vec<Document>,
+    },
+}
+
+fn format_keys(keys: &[Document]) -> String {
+    keys.iter()
+        .map(Document::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Connects to the database and creates its indexes. With
+/// `remove_duplicates`, intervals stored more than once are reduced to their
+/// latest copy when they keep a unique index from being built; otherwise
+/// they fail the initialization.
+pub async fn init_db(
+    uri: &str,
+    db_name: &str,
+    remove_duplicates: bool,
+) -> Result<Database, InitError> {
     let client = Client::with_uri_str(uri).await?;
-    Ok(client.database(db_name))
+    let db = client.database(db_name);
+    create_indexes(&db, remove_duplicates).await?;
+    Ok(db)
+}
+
+/// Creates the unique interval index of every collection, replacing ours if
+/// it was built on an earlier key definition.
+async fn create_indexes(db: &Database, remove_duplicates: bool) -> Result<(), InitError> {
+    for collection_type in CollectionType::ALL {
+        let collection = db.collection::<Document>(collection_type.as_str());
+        let keys: Document = collection_type
+            .unique_key()
+            .iter()
+            .map(|field| (field.to_string(), 1.into()))
+            .collect();
+
+        if drop_stale_unique_index(&collection, &keys).await? {
+            continue;
+        }
+
+        let index = IndexModel::builder()
+            .keys(keys)
+            .options(
+                IndexOptions::builder()
+                    .unique(true)
+                    .name(UNIQUE_INTERVAL_INDEX.to_string())
+                    .build(),
+            )
+            .build();
+
+        match collection.create_index(index.clone(), None).await {
+            Err(e) if is_duplicate_key(&e) => {
+                let duplicates =
+                    find_duplicate_intervals(&collection, collection_type.unique_key()).await?;
+                if !remove_duplicates {
+                    return Err(InitError::DuplicateIntervals {
+                        collection: collection.name().to_string(),
+                        count: duplicates.len(),
+                        keys: duplicates
+                            .into_iter()
+                            .take(REPORTED_DUPLICATES)
+                            .map(|(key, _)| key)
+                            .collect(),
+                    });
+                }
+
+                let removed = remove_duplicate_intervals(&collection, duplicates).await?;
+                log::warn!(
+                    "Removed {} duplicate intervals from {} to build its unique index",
+                    removed,
+                    collection.name()
+                );
+                collection.create_index(index, None).await?;
+            }
+            result => {
+                result?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Drops our `UNIQUE_INTERVAL_INDEX` if its keys differ from `keys`; other
+/// indexes, such as ones created by an operator, are left alone. Returns
+/// whether a unique index on `keys` already exists.
+async fn drop_stale_unique_index(
+    collection: &Collection<Document>,
+    keys: &Document,
+) -> Result<bool, Error> {
+    let mut indexes = match collection.list_indexes(None).await {
+        Ok(indexes) => indexes,
+        // The collection does not exist yet, so neither do its indexes.
+        Err(e) if matches!(*e.kind, ErrorKind::Command(ref c) if c.code == 26) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    let mut up_to_date = false;
+    while let Some(index) = indexes.try_next().await? {
+        let options = index.options.unwrap_or_default();
+        if options.unique != Some(true) {
+            continue;
+        }
+
+        if index.keys == *keys {
+            up_to_date = true;
+        } else if options.name.as_deref() == Some(UNIQUE_INTERVAL_INDEX) {
+            log::info!(
+                "Dropping stale unique index {} on {}",
+                UNIQUE_INTERVAL_INDEX,
+                collection.name()
+            );
+            collection.drop_index(UNIQUE_INTERVAL_INDEX, None).await?;
+        }
+    }
+
+    Ok(up_to_date)
+}
+
+fn is_duplicate_key(error: &Error) -> bool {
+    const DUPLICATE_KEY: i32 = 11000;
+    match &*error.kind {
+        ErrorKind::Command(e) => e.code == DUPLICATE_KEY,
+        ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+        _ => false,
+    }
+}
+
+/// Intervals stored more than once: the key of each, with the ids of its
+/// copies in insertion order.
+async fn find_duplicate_intervals(
+    collection: &Collection<Document>,
+    key_fields: &[&str],
+) -> Result<Vec<(Document, Vec<Bson>)>, Error> {
+    let key: Document = key_fields
+        .iter()
+        .map(|field| (field.to_string(), Bson::String(format!("${}", field))))
+        .collect();
+    let pipeline = vec![
+        doc! { "$sort": { "_id": 1 } },
+        doc! { "$group": { "_id": key, "ids": { "$push": "$_id" } } },
+        doc! { "$match": { "ids.1": { "$exists": true } } },
+        doc! { "$sort": { "_id": 1 } },
+    ];
+    let options = AggregateOptions::builder().allow_disk_use(true).build();
+    let groups: Vec<Document> = collection
+        .aggregate(pipeline, options)
+        .await?
+        .try_collect()
+        .await?;
+
+    Ok(groups
+        .into_iter()
+        .map(|group| {
+            (
+                group.get_document("_id").cloned().unwrap_or_default(),
+                group.get_array("ids").cloned().unwrap_or_default(),
+            )
+        })
+        .collect())
+}
+
+/// Deletes every copy of the `duplicates` but the most recently inserted,
+/// keeping the latest copy of each interval as an upsert would. Returns the
+/// number of documents deleted.
+async fn remove_duplicate_intervals(
+    collection: &Collection<Document>,
+    duplicates: Vec<(Document, Vec<Bson>)>,
+) -> Result<u64, Error> {
+    let mut removed = 0;
+    for (_, mut ids) in duplicates {
+        // Ids are in insertion order for ObjectIds; the last one is kept.
+        ids.pop();
+        removed += collection
+            .delete_many(doc! { "_id": { "$in": ids } }, None)
+            .await?
+            .deleted_count;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_intervals_name_the_collection_and_keys() {
+        let error = InitError::DuplicateIntervals {
+            collection: "swaps".to_string(),
+            count: 7,
+            keys: vec![
+                doc! { "startTime": 1, "pool": "BTC.BTC" },
+                doc! { "startTime": 2, "pool": "BTC.BTC" },
+            ],
+        };
+        let message = error.to_string();
+        assert!(message.starts_with("swaps holds 7 intervals stored more than once"));
+        assert!(message.contains(r#"{ "startTime": 1, "pool": "BTC.BTC" }, { "startTime": 2"#));
+        assert!(message.contains("REMOVE_DUPLICATE_INTERVALS=true"));
+    }
 }
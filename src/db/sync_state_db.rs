@@ -0,0 +1,29 @@
+use crate::models::sync_checkpoint::SyncCheckpoint;
+use bson::doc;
+use mongodb::{error::Error as MongoError, options::ReplaceOptions, Collection, Database};
+
+pub struct SyncStateDB {
+    collection: Collection<SyncCheckpoint>,
+}
+
+impl SyncStateDB {
+    pub const COLLECTION_NAME: &'static str = "sync_state";
+
+    pub fn new(db: &Database) -> Self {
+        Self {
+            collection: db.collection(Self::COLLECTION_NAME),
+        }
+    }
+
+    pub async fn load(&self, series: &str) -> Result<Option<SyncCheckpoint>, MongoError> {
+        self.collection.find_one(doc! { "_id": series }, None).await
+    }
+
+    pub async fn save(&self, checkpoint: &SyncCheckpoint) -> Result<(), MongoError> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        self.collection
+            .replace_one(doc! { "_id": &checkpoint.series }, checkpoint, options)
+            .await?;
+        Ok(())
+    }
+}
@@ -1,7 +1,157 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use mongodb::error::ErrorKind;
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ServiceError {
-    #[error("Database error: {0}")]
-    DatabaseError(#[from] mongodb::error::Error),
+    #[error("{0}")]
+    Validation(String),
+
+    #[error("Invalid query parameters: {}", invalid_parameter_names(.0))]
+    InvalidParameters(Vec<InvalidParameter>),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("Midgard request timed out: {0}")]
+    UpstreamTimeout(String),
+
+    #[error("Midgard request failed: {0}")]
+    Upstream(String),
+
+    #[error("Database unavailable: {0}")]
+    DatabaseUnavailable(String),
+
+    #[error("Stored data does not match the model: {0}")]
+    DataIntegrity(String),
+
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl ServiceError {
+    /// Stable, machine-readable identifier of the error kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Validation(_) | Self::InvalidParameters(_) => "VALIDATION_ERROR",
+            Self::NotFound(_) => "NOT_FOUND",
+            Self::UpstreamTimeout(_) => "UPSTREAM_TIMEOUT",
+            Self::Upstream(_) => "UPSTREAM_ERROR",
+            Self::DatabaseUnavailable(_) => "DATABASE_UNAVAILABLE",
+            Self::DataIntegrity(_) => "DATA_INTEGRITY_ERROR",
+            Self::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Validation(_) | Self::InvalidParameters(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::UpstreamTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::DatabaseUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::DataIntegrity(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Message returned to clients. Details of server-side failures are only
+    /// logged.
+    fn public_message(&self) -> String {
+        match self {
+            Self::Validation(message) | Self::NotFound(message) => message.clone(),
+            Self::InvalidParameters(_) => self.to_string(),
+            Self::UpstreamTimeout(_) => "Midgard did not respond in time".to_string(),
+            Self::Upstream(_) => "Midgard request failed".to_string(),
+            Self::DatabaseUnavailable(_) => "Database is unavailable".to_string(),
+            Self::DataIntegrity(_) => {
+                "Some stored documents could not be read; retry without strict=true to skip them"
+                    .to_string()
+            }
+            Self::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+}
+
+/// One rejected query parameter and why it was rejected.
+#[derive(Debug, Serialize)]
+pub struct InvalidParameter {
+    pub param: String,
+    pub message: String,
+}
+
+fn invalid_parameter_names(params: &[InvalidParameter]) -> String {
+    params
+        .iter()
+        .map(|p| p.param.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl From<mongodb::error::Error> for ServiceError {
+    fn from(e: mongodb::error::Error) -> Self {
+        match *e.kind {
+            ErrorKind::ServerSelection { .. }
+            | ErrorKind::Io(_)
+            | ErrorKind::ConnectionPoolCleared { .. } => Self::DatabaseUnavailable(e.to_string()),
+            _ => Self::Internal(format!("Database error: {}", e)),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ServiceError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::UpstreamTimeout(e.to_string())
+        } else {
+            Self::Upstream(e.to_string())
+        }
+    }
+}
+
+/// A `ServiceError` returned to an HTTP client, tagged with the request id.
+#[derive(Error, Debug)]
+#[error("{error}")]
+pub struct ApiError {
+    pub request_id: String,
+    pub error: ServiceError,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: &'a str,
+    message: String,
+    #[serde(rename = "requestId")]
+    request_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a [InvalidParameter]>,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.error.status()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            log::error!("Request {} failed: {}", self.request_id, self.error);
+        }
+
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: ErrorDetail {
+                code: self.error.code(),
+                message: self.error.public_message(),
+                request_id: &self.request_id,
+                details: match &self.error {
+                    ServiceError::InvalidParameters(params) => Some(params),
+                    _ => None,
+                },
+            },
+        })
+    }
 }
@@ -1,25 +1,411 @@
-use crate::models::{collection_type::CollectionType, query_params::QueryParams};
-use crate::services::data_service::DataService;
-use actix_web::{web, HttpResponse};
-use serde::de::DeserializeOwned;
+use crate::error::{ApiError, ServiceError};
+use crate::models::{
+    collection_type::CollectionType, earnings_model::PoolEarnings, field_registry::FieldRegistry,
+    page::Page, query_params::QueryParams, response::HistoryResponse,
+};
+use crate::services::data_service::{DataService, Dataset};
+use crate::utils::columnar_export::{self, record_batch, write_arrow, write_parquet, BatchEncoder};
+use crate::utils::csv_export::{write_records, CsvColumns, PoolsLayout};
+use crate::utils::query_validation::validate_query;
+use crate::utils::request_id::RequestId;
+use crate::utils::serialization_utils::{document_to_json, OutputProfile};
+use actix_web::{
+    web::{self, Bytes},
+    HttpResponse,
+};
+use arrow_schema::ArrowError;
+use bson::Document;
+use futures::future;
+use futures::stream::{self, BoxStream, Stream, StreamExt, TryStreamExt};
+use parquet::errors::ParquetError;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+const CSV: &str = "text/csv; charset=utf-8";
+const PARQUET: &str = "application/vnd.apache.parquet";
+const ARROW: &str = "application/vnd.apache.arrow.stream";
 
 pub async fn get_data<T>(
     collection_type: CollectionType,
-    query: web::Query<QueryParams>,
+    request_id: RequestId,
+    params: QueryParams,
+    service: web::Data<DataService>,
+) -> Result<HttpResponse, ApiError>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    respond::<T>(
+        Dataset::Collection(collection_type),
+        params,
+        &service,
+        request_id,
+    )
+    .await
+}
+
+/// Same as `get_data`, with the pool taken from the path instead of `pool=`.
+pub async fn get_pool_data<T>(
+    collection_type: CollectionType,
+    request_id: RequestId,
+    path: web::Path<String>,
+    mut params: QueryParams,
+    service: web::Data<DataService>,
+) -> Result<HttpResponse, ApiError>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    params.pool = Some(path.into_inner());
+    respond::<T>(
+        Dataset::Collection(collection_type),
+        params,
+        &service,
+        request_id,
+    )
+    .await
+}
+
+/// Earnings of one or more pools, given as a comma-separated `pool=`, as a
+/// flat series of per-pool rows.
+pub async fn get_pool_earnings(
+    request_id: RequestId,
+    params: QueryParams,
     service: web::Data<DataService>,
-) -> HttpResponse
+) -> Result<HttpResponse, ApiError> {
+    respond::<PoolEarnings>(Dataset::PoolEarnings, params, &service, request_id).await
+}
+
+async fn respond<T>(
+    dataset: Dataset,
+    mut params: QueryParams,
+    service: &DataService,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    let api_error = |error| ApiError {
+        request_id: request_id.0.clone(),
+        error,
+    };
+
+    validate_query(&mut params, dataset.fields(), dataset.is_per_pool()).map_err(api_error)?;
+
+    let selected: Option<Vec<String>> = params
+        .selected_fields()
+        .map(|selected| selected.into_iter().map(str::to_string).collect());
+
+    if matches!(params.format.as_deref(), Some("parquet" | "arrow")) {
+        return respond_columnar(dataset, &params, service, selected, request_id.0.clone())
+            .await
+            .map_err(api_error);
+    }
+
+    if params.format.as_deref() == Some("csv") {
+        return respond_csv::<T>(dataset, &params, service, selected, request_id.0.clone())
+            .await
+            .map_err(api_error);
+    }
+
+    // Selections and other profiles are written from the stored documents;
+    // otherwise the models serialize themselves.
+    let as_objects = selected.is_some() || !params.output_profile().is_default();
+
+    if params.is_streaming() {
+        let response = if as_objects {
+            object_stream::<T>(dataset, &params, service, selected)
+                .await
+                .map(|items| stream_response(items, &params, request_id.0.clone()))
+        } else {
+            service
+                .stream_data::<T>(dataset, &params)
+                .await
+                .map(|items| stream_response(items, &params, request_id.0.clone()))
+        };
+        return response.map_err(api_error);
+    }
+
+    let response = if as_objects {
+        object_page::<T>(dataset, &params, service, selected.as_deref())
+            .await
+            .map(|page| HttpResponse::Ok().json(HistoryResponse::new(page, &params)))
+    } else {
+        service
+            .get_data::<T>(dataset, &params)
+            .await
+            .map(|page| HttpResponse::Ok().json(HistoryResponse::new(page, &params)))
+    };
+    response.map_err(api_error)
+}
+
+/// Every field of `fields`, for output profiles written from the stored
+/// documents without a `fields=` selection.
+fn all_fields(fields: FieldRegistry) -> Vec<String> {
+    fields.iter().map(|field| field.name.to_string()).collect()
+}
+
+/// Results as JSON objects, the way they are serialized in JSON responses.
+async fn object_page<T>(
+    dataset: Dataset,
+    params: &QueryParams,
+    service: &DataService,
+    selected: Option<&[String]>,
+) -> Result<Page<Map<String, Value>>, ServiceError>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let profile = params.output_profile();
+    if selected.is_some() || !profile.is_default() {
+        let fields = dataset.fields();
+        let selected = selected.map_or_else(|| all_fields(fields), <[String]>::to_vec);
+        let page = service.get_data::<Document>(dataset, params).await?;
+        return Ok(project_page(page, fields, &selected, profile));
+    }
+
+    let page = service.get_data::<T>(dataset, params).await?;
+    Ok(Page {
+        items: page.items.iter().map(to_object).collect::<Result<_, _>>()?,
+        next: page.next,
+        limit: page.limit,
+        skipped: page.skipped,
+    })
+}
+
+/// Streaming counterpart of `object_page`.
+async fn object_stream<T>(
+    dataset: Dataset,
+    params: &QueryParams,
+    service: &DataService,
+    selected: Option<Vec<String>>,
+) -> Result<BoxStream<'static, Result<Map<String, Value>, ServiceError>>, ServiceError>
 where
-    T: serde::Serialize + DeserializeOwned,
+    T: Serialize + DeserializeOwned + Send + 'static,
 {
-    match service.get_data::<T>(collection_type, &query).await {
-        Ok(data) => HttpResponse::Ok().json(data),
-        Err(e) => {
-            log::error!(
-                "Failed to fetch data from {}: {}",
-                collection_type.as_str(),
-                e
-            );
-            HttpResponse::InternalServerError().finish()
+    let fields = dataset.fields();
+    let profile = params.output_profile();
+    let selected = match selected {
+        None if !profile.is_default() => Some(all_fields(fields)),
+        selected => selected,
+    };
+    Ok(match selected {
+        Some(selected) => service
+            .stream_data::<Document>(dataset, params)
+            .await?
+            .map_ok(move |document| document_to_json(document, fields, &selected, profile))
+            .boxed(),
+        None => service
+            .stream_data::<T>(dataset, params)
+            .await?
+            .and_then(|item| future::ready(to_object(&item)))
+            .boxed(),
+    })
+}
+
+fn to_object<T: Serialize>(item: &T) -> Result<Map<String, Value>, ServiceError> {
+    match serde_json::to_value(item) {
+        Ok(Value::Object(object)) => Ok(object),
+        Ok(_) => Ok(Map::new()),
+        Err(error) => Err(ServiceError::Internal(error.to_string())),
+    }
+}
+
+/// Results as CSV, with a header line of serde field names. Pages carry the
+/// next page's cursor in an `X-Next-Cursor` header; streams are sent in the
+/// long layout as they are read.
+async fn respond_csv<T>(
+    dataset: Dataset,
+    params: &QueryParams,
+    service: &DataService,
+    selected: Option<Vec<String>>,
+    request_id: String,
+) -> Result<HttpResponse, ServiceError>
+where
+    T: Serialize + DeserializeOwned + Send + 'static,
+{
+    let columns = CsvColumns::new(dataset.fields(), selected.as_deref());
+    let csv_error = |error: csv::Error| ServiceError::Internal(error.to_string());
+
+    if params.is_streaming() {
+        let header = write_records([columns.long_header()]).map_err(csv_error)?;
+        let lines = object_stream::<T>(dataset, params, service, selected)
+            .await?
+            .map(move |item| {
+                let lines = item
+                    .and_then(|item| write_records(columns.long_records(&item)).map_err(csv_error));
+                lines.map(Bytes::from).map_err(|error| {
+                    log::error!("Request {} stream failed: {}", request_id, error);
+                    ApiError {
+                        request_id: request_id.clone(),
+                        error,
+                    }
+                })
+            });
+        let body = stream::once(future::ready(Ok(Bytes::from(header)))).chain(lines);
+        return Ok(HttpResponse::Ok().content_type(CSV).streaming(body));
+    }
+
+    let page = object_page::<T>(dataset, params, service, selected.as_deref()).await?;
+    let table = match params.pools_layout() {
+        PoolsLayout::Long => {
+            let mut table = vec![columns.long_header()];
+            for item in &page.items {
+                table.extend(columns.long_records(item));
+            }
+            table
         }
+        PoolsLayout::Wide => columns.wide_table(&page.items),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response.content_type(CSV);
+    if let Some(next) = page.next {
+        response.insert_header(("X-Next-Cursor", next));
     }
+    Ok(response.body(write_records(table).map_err(csv_error)?))
+}
+
+/// Results as Parquet or an Arrow IPC stream, with a typed column per field.
+/// Pages carry the next page's cursor in an `X-Next-Cursor` header. When
+/// streaming, results are encoded in batches of `BATCH_ROWS` as they are
+/// read and each batch is sent as soon as it is encoded, as a Parquet row
+/// group or an Arrow message.
+async fn respond_columnar(
+    dataset: Dataset,
+    params: &QueryParams,
+    service: &DataService,
+    selected: Option<Vec<String>>,
+    request_id: String,
+) -> Result<HttpResponse, ServiceError> {
+    const BATCH_ROWS: usize = 8192;
+
+    let parquet = params.format.as_deref() == Some("parquet");
+    let content_type = if parquet { PARQUET } else { ARROW };
+    let (schema, fields) = columnar_export::schema(dataset.fields(), selected.as_deref());
+    let arrow_error = |error: ArrowError| ServiceError::Internal(error.to_string());
+    let parquet_error = |error: ParquetError| ServiceError::Internal(error.to_string());
+
+    if !params.is_streaming() {
+        let page = service.get_data::<Document>(dataset, params).await?;
+        let batch = record_batch(&schema, &fields, &page.items).map_err(arrow_error)?;
+        let body = if parquet {
+            write_parquet(&schema, &[batch]).map_err(parquet_error)?
+        } else {
+            write_arrow(&schema, &[batch]).map_err(arrow_error)?
+        };
+
+        let mut response = HttpResponse::Ok();
+        response.content_type(content_type);
+        if let Some(next) = page.next {
+            response.insert_header(("X-Next-Cursor", next));
+        }
+        return Ok(response.body(body));
+    }
+
+    let chunks = service
+        .stream_data::<Document>(dataset, params)
+        .await?
+        .try_chunks(BATCH_ROWS)
+        .map_err(|error| error.1);
+
+    let encoder = if parquet {
+        BatchEncoder::parquet(&schema)
+    } else {
+        BatchEncoder::arrow(&schema)
+    }
+    .map_err(parquet_error)?;
+    let body = stream::unfold(Some((chunks, encoder)), move |state| {
+        let schema = schema.clone();
+        let fields = fields.clone();
+        let request_id = request_id.clone();
+        async move {
+            let (mut chunks, mut encoder) = state?;
+            let (written, next) = match chunks.next().await {
+                Some(Ok(documents)) => {
+                    let written = record_batch(&schema, &fields, &documents)
+                        .map_err(arrow_error)
+                        .and_then(|batch| encoder.write(&batch).map_err(parquet_error));
+                    let more = written.is_ok();
+                    (written, more.then_some(encoder))
+                }
+                Some(Err(error)) => (Err(error), None),
+                None => (encoder.finish().map_err(parquet_error), None),
+            };
+            let chunk = written.map(Bytes::from).map_err(|error| {
+                log::error!("Request {} stream failed: {}", request_id, error);
+                ApiError {
+                    request_id: request_id.clone(),
+                    error,
+                }
+            });
+            Some((chunk, next.map(|encoder| (chunks, encoder))))
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .streaming(body))
+}
+
+/// Keeps only the `selected` fields of each document, written in `profile`.
+/// Projected documents lack the model's other fields, so they are
+/// serialized by the registry rather than through the model.
+fn project_page(
+    page: Page<Document>,
+    fields: FieldRegistry,
+    selected: &[String],
+    profile: OutputProfile,
+) -> Page<Map<String, Value>> {
+    Page {
+        items: page
+            .items
+            .into_iter()
+            .map(|document| document_to_json(document, fields, selected, profile))
+            .collect(),
+        next: page.next,
+        limit: page.limit,
+        skipped: page.skipped,
+    }
+}
+
+/// Streams `items` as they are read, one JSON value per line for NDJSON or
+/// as the elements of a JSON array. An error after the first bytes are sent
+/// can only end the body early, so it is logged and cuts the stream short.
+fn stream_response<S, T>(items: S, params: &QueryParams, request_id: String) -> HttpResponse
+where
+    S: Stream<Item = Result<T, ServiceError>> + 'static,
+    T: Serialize,
+{
+    let ndjson = params.format.as_deref() == Some("ndjson");
+
+    let body = items.enumerate().map(move |(index, item)| {
+        let item = item.map_err(|error| {
+            log::error!("Request {} stream failed: {}", request_id, error);
+            ApiError {
+                request_id: request_id.clone(),
+                error,
+            }
+        })?;
+        let mut chunk = match (ndjson, index) {
+            (true, _) | (false, 0) => Vec::new(),
+            (false, _) => b",".to_vec(),
+        };
+        serde_json::to_writer(&mut chunk, &item).map_err(|error| ApiError {
+            request_id: request_id.clone(),
+            error: ServiceError::Internal(error.to_string()),
+        })?;
+        if ndjson {
+            chunk.push(b'\n');
+        }
+        Ok::<_, ApiError>(Bytes::from(chunk))
+    });
+
+    if ndjson {
+        return HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .streaming(body);
+    }
+
+    let open = stream::once(async { Ok(Bytes::from_static(b"[")) });
+    let close = stream::once(async { Ok(Bytes::from_static(b"]")) });
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .streaming(open.chain(body).chain(close))
 }
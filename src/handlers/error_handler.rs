@@ -0,0 +1,20 @@
+use crate::error::{ApiError, ServiceError};
+use crate::utils::request_id::RequestId;
+use actix_web::{error::QueryPayloadError, HttpRequest, HttpResponse};
+
+/// Reports malformed query strings (e.g. `limit=abc`) as validation errors.
+pub fn query_error_handler(err: QueryPayloadError, req: &HttpRequest) -> actix_web::Error {
+    ApiError {
+        request_id: RequestId::of(req).0,
+        error: ServiceError::Validation(err.to_string()),
+    }
+    .into()
+}
+
+/// Fallback for routes that do not exist.
+pub async fn not_found(request_id: RequestId, req: HttpRequest) -> Result<HttpResponse, ApiError> {
+    Err(ApiError {
+        request_id: request_id.0,
+        error: ServiceError::NotFound(format!("No route for {}", req.path())),
+    })
+}
@@ -0,0 +1,9 @@
+use crate::utils::metrics;
+use actix_web::HttpResponse;
+
+/// Service metrics in the Prometheus text format.
+pub async fn get_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render())
+}
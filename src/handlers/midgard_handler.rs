@@ -0,0 +1,237 @@
+use crate::db::base_db::BaseDB;
+use crate::error::{ApiError, InvalidParameter, ServiceError};
+use crate::models::{collection_type::CollectionType, query_params::QueryParams};
+use crate::services::data_service::{DataService, Dataset};
+use crate::utils::group_stage::INTERVALS;
+use crate::utils::midgard_response::{
+    depth_meta, interval_to_json, meta_to_json, runepool_meta, Summary,
+};
+use crate::utils::query_validation::validate_query;
+use crate::utils::request_id::RequestId;
+use actix_web::{web, HttpResponse};
+use bson::Document;
+use futures::stream::TryStreamExt;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Query parameters of Midgard's `/v2/history` endpoints. `from` and `to`
+/// are unix seconds.
+#[derive(Debug, Deserialize)]
+pub struct MidgardQuery {
+    pub interval: Option<String>,
+    pub count: Option<i64>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub pool: Option<String>,
+}
+
+/// Midgard-compatible history, with the pool, where accepted, taken from
+/// `pool=` as Midgard does for swaps.
+pub async fn get_history(
+    collection_type: CollectionType,
+    request_id: RequestId,
+    query: web::Query<MidgardQuery>,
+    service: web::Data<DataService>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = query.pool.clone();
+    respond(collection_type, pool, &query, &service, request_id).await
+}
+
+/// Same as `get_history`, with the pool taken from the path as Midgard does
+/// for depths.
+pub async fn get_pool_history(
+    collection_type: CollectionType,
+    request_id: RequestId,
+    path: web::Path<String>,
+    query: web::Query<MidgardQuery>,
+    service: web::Data<DataService>,
+) -> Result<HttpResponse, ApiError> {
+    respond(
+        collection_type,
+        Some(path.into_inner()),
+        &query,
+        &service,
+        request_id,
+    )
+    .await
+}
+
+/// Translates Midgard's parameters into a history query. With an
+/// interval, up to `count` buckets are read from `from` onwards, or the
+/// latest ones up to `to` (or now) when `from` is not given. Without one,
+/// the range from `from` is read to be combined into a single interval, or
+/// without `from` the latest `MAX_RECORDS_NO_FILTER` stored intervals, so
+/// a bare request never summarises the whole collection.
+fn history_params(query: &MidgardQuery, pool: Option<String>) -> Result<QueryParams, ServiceError> {
+    let max_count = BaseDB::MAX_RECORDS_NO_FILTER;
+    let mut invalid = Vec::new();
+    let mut reject = |param: &str, message: String| {
+        invalid.push(InvalidParameter {
+            param: param.to_string(),
+            message,
+        })
+    };
+
+    if let Some(interval) = &query.interval {
+        if !INTERVALS.contains(&interval.as_str()) {
+            reject(
+                "interval",
+                format!(
+                    "'{}' must be one of {}; shorter intervals are not stored",
+                    interval,
+                    INTERVALS.join(", ")
+                ),
+            );
+        }
+    }
+
+    if let Some(count) = query.count {
+        if query.interval.is_none() {
+            reject("count", "requires an interval".to_string());
+        } else if query.from.is_some() && query.to.is_some() {
+            reject(
+                "count",
+                "cannot be combined with both from and to".to_string(),
+            );
+        } else if !(1..=max_count).contains(&count) {
+            reject(
+                "count",
+                format!("{} must be between 1 and {}", count, max_count),
+            );
+        }
+    }
+
+    if !invalid.is_empty() {
+        return Err(ServiceError::InvalidParameters(invalid));
+    }
+
+    let latest = query.from.is_none();
+    Ok(QueryParams {
+        from: query.from.map(|from| from.to_string()),
+        to: query.to.map(|to| to.to_string()),
+        range_mode: Some("overlapping".to_string()),
+        sort_by: Some("startTime".to_string()),
+        order: Some(if latest { "desc" } else { "asc" }.to_string()),
+        limit: (query.interval.is_some() || latest).then(|| query.count.unwrap_or(max_count)),
+        pool,
+        interval: query.interval.clone(),
+        ..Default::default()
+    })
+}
+
+async fn respond(
+    collection_type: CollectionType,
+    pool: Option<String>,
+    query: &MidgardQuery,
+    service: &DataService,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let api_error = |error| ApiError {
+        request_id: request_id.0.clone(),
+        error,
+    };
+
+    let fields = collection_type.fields();
+    let mut params = history_params(query, pool).map_err(api_error)?;
+    validate_query(&mut params, fields, collection_type.is_per_pool()).map_err(api_error)?;
+
+    let dataset = Dataset::Collection(collection_type);
+    let mut summary = Summary::new(fields);
+    let (intervals, first, last) = if params.limit.is_some() {
+        let page = service
+            .get_data::<Document>(dataset, &params)
+            .await
+            .map_err(api_error)?;
+        if page.next.is_some() && query.from.is_some() && query.to.is_some() {
+            return Err(api_error(ServiceError::Validation(format!(
+                "from and to span more than {} intervals",
+                page.limit
+            ))));
+        }
+
+        let mut intervals = page.items;
+        if params.order.as_deref() == Some("desc") {
+            intervals.reverse();
+        }
+        intervals.iter().for_each(|interval| summary.add(interval));
+        let (first, last) = (intervals.first().cloned(), intervals.last().cloned());
+        (query.interval.is_some().then_some(intervals), first, last)
+    } else {
+        // The range is combined as it is read rather than held in memory.
+        let mut documents = service
+            .stream_data::<Document>(dataset, &params)
+            .await
+            .map_err(api_error)?;
+        let (mut first, mut last) = (None, None);
+        while let Some(document) = documents.try_next().await.map_err(api_error)? {
+            summary.add(&document);
+            if first.is_none() {
+                first = Some(document.clone());
+            }
+            last = Some(document);
+        }
+        (None, first, last)
+    };
+
+    // Swaps and earnings describe the whole range like one interval; without
+    // an interval, that is also the only one returned.
+    let combined = interval_to_json(summary.finish(), fields);
+    let meta = match collection_type {
+        CollectionType::Depths => meta_to_json(depth_meta(first.as_ref(), last.as_ref())),
+        CollectionType::Runepools => meta_to_json(runepool_meta(first.as_ref(), last.as_ref())),
+        CollectionType::Swaps | CollectionType::Earnings => combined.clone(),
+    };
+    let intervals: Vec<Value> = match intervals {
+        Some(intervals) => intervals
+            .into_iter()
+            .map(|interval| Value::Object(interval_to_json(interval, fields)))
+            .collect(),
+        None => vec![Value::Object(combined)],
+    };
+
+    let mut body = Map::new();
+    body.insert("intervals".to_string(), Value::Array(intervals));
+    body.insert("meta".to_string(), Value::Object(meta));
+    Ok(HttpResponse::Ok().json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(interval: Option<&str>, from: Option<i64>, to: Option<i64>) -> MidgardQuery {
+        MidgardQuery {
+            interval: interval.map(str::to_string),
+            count: None,
+            from,
+            to,
+            pool: None,
+        }
+    }
+
+    #[test]
+    fn summaries_without_from_read_the_latest_intervals() {
+        for to in [None, Some(1_730_419_200)] {
+            let params = history_params(&query(None, None, to), None).unwrap();
+            assert_eq!(params.limit, Some(BaseDB::MAX_RECORDS_NO_FILTER));
+            assert_eq!(params.order.as_deref(), Some("desc"));
+        }
+
+        let params = history_params(&query(None, Some(1_730_419_200), None), None).unwrap();
+        assert_eq!(params.limit, None);
+        assert_eq!(params.order.as_deref(), Some("asc"));
+    }
+
+    #[test]
+    fn intervals_read_count_buckets() {
+        let mut latest = query(Some("day"), None, None);
+        latest.count = Some(30);
+        let params = history_params(&latest, None).unwrap();
+        assert_eq!(params.limit, Some(30));
+        assert_eq!(params.order.as_deref(), Some("desc"));
+
+        let params = history_params(&query(Some("day"), Some(1_730_419_200), None), None).unwrap();
+        assert_eq!(params.limit, Some(BaseDB::MAX_RECORDS_NO_FILTER));
+        assert_eq!(params.order.as_deref(), Some("asc"));
+    }
+}
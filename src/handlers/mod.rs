@@ -1 +1,4 @@
 pub mod data_handler;
+pub mod error_handler;
+pub mod metrics_handler;
+pub mod midgard_handler;
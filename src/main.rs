@@ -1,4 +1,3 @@
-use env_logger;
 use log::info;
 
 mod db;
@@ -16,19 +15,32 @@ async fn main() -> std::io::Result<()> {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
 
     // Load configuration
-    let config = utils::config::AppConfig::from_env()
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.0))?;
+    let config = utils::config::AppConfig::from_env().map_err(|e| std::io::Error::other(e.0))?;
 
     info!("Starting server with configuration: {:?}", config);
 
     // Initialize application state
     let state = state::AppState::new(&config)
         .await
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.0))?;
+        .map_err(|e| std::io::Error::other(e.0))?;
+
+    // Keep the collections in sync with Midgard in the background
+    let schedule = models::collection_type::CollectionType::ALL
+        .into_iter()
+        .map(|collection_type| (collection_type, config.sync_interval(collection_type)))
+        .collect();
+    let sync_worker =
+        services::sync_worker::SyncWorker::start(state.ingestion_service.clone(), schedule);
 
     // Start server
     let server = utils::server::setup_app(config, state).await?;
     info!("Server started successfully");
 
-    server.await
+    let result = server.await;
+
+    // Stop syncing once the server has shut down
+    sync_worker.shutdown().await;
+    info!("Sync worker stopped");
+
+    result
 }
@@ -1,3 +1,7 @@
+use crate::models::field_registry::FieldRegistry;
+use crate::models::{
+    depth_model::Depth, earnings_model::Earnings, runepools_model::RunePool, swaps_model::Swaps,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -9,6 +13,9 @@ pub enum CollectionType {
 }
 
 impl CollectionType {
+    pub const ALL: [CollectionType; 4] =
+        [Self::Depths, Self::Swaps, Self::Runepools, Self::Earnings];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             Self::Depths => "depths",
@@ -17,4 +24,35 @@ impl CollectionType {
             Self::Earnings => "earnings",
         }
     }
+
+    /// Whether documents carry a `pool` field and can be filtered by pool.
+    pub fn is_per_pool(&self) -> bool {
+        matches!(self, Self::Depths | Self::Swaps)
+    }
+
+    /// Whether the collection stores a series aggregated across all pools.
+    pub fn has_aggregate_series(&self) -> bool {
+        !matches!(self, Self::Depths)
+    }
+
+    /// Fields of the collection's model: their stored and Rust names, types
+    /// and how `interval=` combines them. Used to resolve `sort_by`.
+    pub fn fields(&self) -> FieldRegistry {
+        match self {
+            Self::Depths => Depth::FIELDS,
+            Self::Swaps => Swaps::FIELDS,
+            Self::Runepools => RunePool::FIELDS,
+            Self::Earnings => Earnings::FIELDS,
+        }
+    }
+
+    /// Fields identifying one stored interval. Backed by a unique index and
+    /// used as the upsert filter, so re-ingesting an interval never
+    /// duplicates it.
+    pub fn unique_key(&self) -> &'static [&'static str] {
+        match self {
+            Self::Depths | Self::Swaps => &["pool", "startTime", "endTime"],
+            Self::Runepools | Self::Earnings => &["startTime", "endTime"],
+        }
+    }
 }
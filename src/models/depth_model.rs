@@ -1,3 +1,4 @@
+use crate::models::field_registry::{Field, FieldRegistry, FieldType};
 use crate::utils::serialization_utils::{
     serialize_datetime_as_timestamp, serialize_decimal_as_string,
 };
@@ -28,6 +29,9 @@ pub struct Depth {
     #[serde(rename = "membersCount")]
     pub members_count: i32,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
     #[serde(rename = "runeDepth")]
     pub rune_depth: i64,
 
@@ -43,3 +47,21 @@ pub struct Depth {
 
     pub units: i64,
 }
+
+impl Depth {
+    pub const FIELDS: FieldRegistry = &[
+        Field::gauge("assetDepth", "asset_depth", FieldType::Decimal),
+        Field::gauge("assetPrice", "asset_price", FieldType::Double),
+        Field::gauge("assetPriceUSD", "asset_price_usd", FieldType::Double),
+        Field::key("endTime", "end_time", FieldType::DateTime),
+        Field::gauge("liquidityUnits", "liquidity_units", FieldType::Int64),
+        Field::gauge("luvi", "luvi", FieldType::Double),
+        Field::gauge("membersCount", "members_count", FieldType::Int32),
+        Field::key("pool", "pool", FieldType::Text),
+        Field::gauge("runeDepth", "rune_depth", FieldType::Int64),
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::gauge("synthSupply", "synth_supply", FieldType::Int64),
+        Field::gauge("synthUnits", "synth_units", FieldType::Int64),
+        Field::gauge("units", "units", FieldType::Int64),
+    ];
+}
@@ -1,3 +1,5 @@
+use crate::models::field_registry::{Field, FieldRegistry, FieldType};
+use crate::utils::serialization_utils::serialize_datetime_as_timestamp;
 use bson::DateTime as BsonDateTime;
 use serde::{Deserialize, Serialize, Serializer}; // Added Serializer import
 
@@ -49,6 +51,21 @@ pub struct Earnings {
     pub pools: Vec<LiquidityPool>, // array
 }
 
+impl Earnings {
+    pub const FIELDS: FieldRegistry = &[
+        Field::average("avgNodeCount", "avg_node_count", FieldType::Double),
+        Field::counter("blockRewards", "block_rewards", FieldType::Int64),
+        Field::counter("bondingEarnings", "bonding_earnings", FieldType::Int64),
+        Field::counter("earnings", "earnings", FieldType::Int64),
+        Field::key("endTime", "end_time", FieldType::DateTime),
+        Field::counter("liquidityEarnings", "liquidity_earnings", FieldType::Int64),
+        Field::counter("liquidityFees", "liquidity_fees", FieldType::Int64),
+        Field::gauge("runePriceUSD", "rune_price_usd", FieldType::NumericText),
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::pools("pools", "pools", LiquidityPool::FIELDS),
+    ];
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LiquidityPool {
     #[serde(rename = "assetLiquidityFees")]
@@ -78,3 +95,62 @@ pub struct LiquidityPool {
     #[serde(rename = "totalLiquidityFeesRune")]
     pub total_liquidity_fees_rune: i64, // long
 }
+
+impl LiquidityPool {
+    pub const FIELDS: FieldRegistry = &[
+        Field::counter(
+            "assetLiquidityFees",
+            "asset_liquidity_fees",
+            FieldType::Int64,
+        ),
+        Field::counter("earnings", "earnings", FieldType::Int64),
+        Field::key("pool", "pool", FieldType::Text),
+        Field::counter("rewards", "rewards", FieldType::Int64),
+        Field::counter("runeLiquidityFees", "rune_liquidity_fees", FieldType::Int64),
+        Field::key("runePriceUSD", "rune_price_usd", FieldType::NumericText),
+        Field::counter("saverEarning", "saver_earning", FieldType::Int64),
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::counter(
+            "totalLiquidityFeesRune",
+            "total_liquidity_fees_rune",
+            FieldType::Int64,
+        ),
+    ];
+}
+
+/// One pool's earnings for an interval, flattened out of `Earnings.pools`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoolEarnings {
+    #[serde(rename = "startTime")]
+    #[serde(serialize_with = "serialize_datetime_as_timestamp")]
+    pub start_time: BsonDateTime,
+
+    #[serde(rename = "endTime")]
+    #[serde(serialize_with = "serialize_datetime_as_timestamp")]
+    pub end_time: BsonDateTime,
+
+    #[serde(flatten)]
+    pub pool: LiquidityPool,
+}
+
+impl PoolEarnings {
+    pub const FIELDS: FieldRegistry = &[
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::key("endTime", "end_time", FieldType::DateTime),
+        Field::key("pool", "pool", FieldType::Text),
+        Field::counter(
+            "assetLiquidityFees",
+            "asset_liquidity_fees",
+            FieldType::Int64,
+        ),
+        Field::counter("earnings", "earnings", FieldType::Int64),
+        Field::counter("rewards", "rewards", FieldType::Int64),
+        Field::counter("runeLiquidityFees", "rune_liquidity_fees", FieldType::Int64),
+        Field::counter("saverEarning", "saver_earning", FieldType::Int64),
+        Field::counter(
+            "totalLiquidityFeesRune",
+            "total_liquidity_fees_rune",
+            FieldType::Int64,
+        ),
+    ];
+}
@@ -0,0 +1,250 @@
+use bson::{Bson, Document};
+
+/// BSON type a model field is stored as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    DateTime,
+    Decimal,
+    Double,
+    Int32,
+    Int64,
+    Text,
+    /// A number kept as text the way Midgard sends it, e.g.
+    /// `Earnings.runePriceUSD`.
+    NumericText,
+    /// Array of nested rows described by their own registry.
+    Array(FieldRegistry),
+}
+
+impl FieldType {
+    /// Whether a stored value can be read as this type. Integers are
+    /// accepted where wider numbers are declared, as serde does.
+    pub fn accepts(&self, value: &Bson) -> bool {
+        matches!(
+            (self, value),
+            (Self::DateTime, Bson::DateTime(_))
+                | (Self::Decimal, Bson::Decimal128(_))
+                | (
+                    Self::Double,
+                    Bson::Double(_) | Bson::Int32(_) | Bson::Int64(_)
+                )
+                | (Self::Int64, Bson::Int32(_) | Bson::Int64(_))
+                | (Self::Int32, Bson::Int32(_))
+                | (Self::Text | Self::NumericText, Bson::String(_))
+                | (Self::Array(_), Bson::Array(_))
+        )
+    }
+
+    /// Whether the field can be compared with a number in a filter.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Self::Decimal | Self::Double | Self::Int32 | Self::Int64
+        )
+    }
+}
+
+/// How a field is combined when stored intervals are grouped into a larger
+/// calendar bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation {
+    /// Counters such as volumes, fees and counts.
+    Sum,
+    /// Gauges such as depths, prices and units: the value of the latest interval.
+    Last,
+    /// Averages of a per-interval value, e.g. node counts.
+    Avg,
+    /// Averages over the events counted by the named counter, e.g. slips
+    /// weighted by their swap counts: `sum(value * count) / sum(count)`.
+    WeightedAvg(&'static str),
+    /// An array of per-pool rows, combined per `pool` by the rows' registry.
+    PerPool,
+}
+
+/// One field of a model: its stored (serde) name, its Rust name, stored type
+/// and aggregation. Interval bounds and `pool` are grouping keys and have no
+/// aggregation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Field {
+    pub name: &'static str,
+    pub rust_name: &'static str,
+    pub field_type: FieldType,
+    pub aggregation: Option<Aggregation>,
+}
+
+pub type FieldRegistry = &'static [Field];
+
+impl Field {
+    pub const fn key(name: &'static str, rust_name: &'static str, field_type: FieldType) -> Self {
+        Self {
+            name,
+            rust_name,
+            field_type,
+            aggregation: None,
+        }
+    }
+
+    pub const fn counter(
+        name: &'static str,
+        rust_name: &'static str,
+        field_type: FieldType,
+    ) -> Self {
+        Self {
+            name,
+            rust_name,
+            field_type,
+            aggregation: Some(Aggregation::Sum),
+        }
+    }
+
+    pub const fn gauge(name: &'static str, rust_name: &'static str, field_type: FieldType) -> Self {
+        Self {
+            name,
+            rust_name,
+            field_type,
+            aggregation: Some(Aggregation::Last),
+        }
+    }
+
+    pub const fn average(
+        name: &'static str,
+        rust_name: &'static str,
+        field_type: FieldType,
+    ) -> Self {
+        Self {
+            name,
+            rust_name,
+            field_type,
+            aggregation: Some(Aggregation::Avg),
+        }
+    }
+
+    /// An average over the events counted by `weight`, a counter of the
+    /// same registry.
+    pub const fn weighted_average(
+        name: &'static str,
+        rust_name: &'static str,
+        field_type: FieldType,
+        weight: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            rust_name,
+            field_type,
+            aggregation: Some(Aggregation::WeightedAvg(weight)),
+        }
+    }
+
+    pub const fn pools(name: &'static str, rust_name: &'static str, rows: FieldRegistry) -> Self {
+        Self {
+            name,
+            rust_name,
+            field_type: FieldType::Array(rows),
+            aggregation: Some(Aggregation::PerPool),
+        }
+    }
+
+    /// Arrays have no meaningful order, every other field can be sorted on.
+    pub fn is_sortable(&self) -> bool {
+        !matches!(self.field_type, FieldType::Array(_))
+    }
+}
+
+/// Path of the first field of `document` whose stored value does not have
+/// the declared type, e.g. `pools.3.earnings`. Missing and null fields are
+/// not reported, since some fields are optional.
+pub fn find_mismatch(document: &Document, registry: FieldRegistry) -> Option<String> {
+    for field in registry {
+        let value = match document.get(field.name) {
+            None | Some(Bson::Null) => continue,
+            Some(value) => value,
+        };
+        if !field.field_type.accepts(value) {
+            return Some(field.name.to_string());
+        }
+        if let (FieldType::Array(rows), Bson::Array(values)) = (field.field_type, value) {
+            for (index, row) in values.iter().enumerate() {
+                let path = match row {
+                    Bson::Document(row) => find_mismatch(row, rows),
+                    _ => Some(String::new()),
+                };
+                if let Some(path) = path {
+                    let row_path = format!("{}.{}", field.name, index);
+                    return Some(if path.is_empty() {
+                        row_path
+                    } else {
+                        format!("{}.{}", row_path, path)
+                    });
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Looks a field up by its stored camelCase name or its snake_case Rust name.
+pub fn resolve_field(registry: FieldRegistry, name: &str) -> Option<&'static Field> {
+    registry
+        .iter()
+        .find(|field| field.name == name || field.rust_name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::earnings_model::Earnings;
+    use crate::models::swaps_model::Swaps;
+    use bson::doc;
+
+    #[test]
+    fn fields_resolve_by_stored_or_rust_name() {
+        let by_rust_name = resolve_field(Swaps::FIELDS, "total_volume_usd").unwrap();
+        assert_eq!(by_rust_name.name, "totalVolumeUSD");
+        let by_name = resolve_field(Swaps::FIELDS, "totalVolumeUSD").unwrap();
+        assert_eq!(by_name, by_rust_name);
+
+        assert!(resolve_field(Swaps::FIELDS, "TotalVolumeUSD").is_none());
+        assert!(resolve_field(Swaps::FIELDS, "total_volume_USD").is_none());
+    }
+
+    #[test]
+    fn mismatches_are_reported_by_path() {
+        let valid = doc! {
+            "earnings": 10_i64,
+            "avgNodeCount": 3,
+            "runePriceUSD": "1.5",
+            "pools": [{ "pool": "BTC.BTC", "earnings": 5 }],
+        };
+        assert_eq!(find_mismatch(&valid, Earnings::FIELDS), None);
+
+        // Missing and null fields are not checked.
+        assert_eq!(
+            find_mismatch(&doc! { "earnings": Bson::Null }, Earnings::FIELDS),
+            None
+        );
+
+        assert_eq!(
+            find_mismatch(&doc! { "earnings": "10" }, Earnings::FIELDS),
+            Some("earnings".to_string())
+        );
+        assert_eq!(
+            find_mismatch(&doc! { "earnings": 1.5 }, Earnings::FIELDS),
+            Some("earnings".to_string())
+        );
+
+        let pools = doc! {
+            "pools": [
+                { "pool": "BTC.BTC", "earnings": 5 },
+                { "pool": "ETH.ETH", "earnings": "5" },
+            ],
+        };
+        assert_eq!(
+            find_mismatch(&pools, Earnings::FIELDS),
+            Some("pools.1.earnings".to_string())
+        );
+        assert_eq!(
+            find_mismatch(&doc! { "pools": ["BTC.BTC"] }, Earnings::FIELDS),
+            Some("pools.0".to_string())
+        );
+    }
+}
@@ -1,6 +1,10 @@
 pub mod collection_type;
 pub mod depth_model;
 pub mod earnings_model;
+pub mod field_registry;
+pub mod page;
 pub mod query_params;
+pub mod response;
 pub mod runepools_model;
 pub mod swaps_model;
+pub mod sync_checkpoint;
@@ -0,0 +1,10 @@
+/// One page of results, with the cursor of the following page if there is one.
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+    /// Page size the results were read with.
+    pub limit: i64,
+    /// Documents of the page left out because they did not match the model.
+    pub skipped: u64,
+}
@@ -1,9 +1,186 @@
+use crate::utils::csv_export::PoolsLayout;
+use crate::utils::date_utils::{parse_bounds, parse_date_range, DateRange, RangeMode};
+use crate::utils::serialization_utils::{OutputProfile, ProfileVersion, TimestampFormat};
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+/// Response formats accepted in `format=`.
+pub const FORMATS: [&str; 5] = ["json", "ndjson", "csv", "parquet", "arrow"];
+
+/// Comparison operators accepted in `field[op]=value` filters.
+pub const FILTER_OPERATORS: [&str; 5] = ["gt", "gte", "lt", "lte", "eq"];
+
+#[derive(Debug, Default, Deserialize)]
 pub struct QueryParams {
     pub date_range: Option<String>,
+    /// Start of the range, as an alternative to `date_range`.
+    pub from: Option<String>,
+    /// End of the range, as an alternative to `date_range`.
+    pub to: Option<String>,
+    /// `contained` (default) or `overlapping`, see `RangeMode`.
+    pub range_mode: Option<String>,
     pub sort_by: Option<String>,
     pub order: Option<String>,
     pub limit: Option<i64>,
+    pub pool: Option<String>,
+    pub interval: Option<String>,
+    pub cursor: Option<String>,
+    /// Fail instead of skipping stored documents that do not match the model.
+    pub strict: Option<bool>,
+    /// Response format, see `FORMATS`. Taken from the `Accept` header when
+    /// not given.
+    pub format: Option<String>,
+    /// Stream every result instead of returning a page.
+    pub stream: Option<bool>,
+    /// Layout of `Earnings.pools` in CSV exports, see `PoolsLayout`.
+    pub pools_layout: Option<String>,
+    /// Comma-separated fields to return instead of the whole model.
+    pub fields: Option<String>,
+    /// Version of the JSON output, see `ProfileVersion`.
+    pub profile: Option<String>,
+    /// How timestamps are written, see `TimestampFormat`.
+    pub ts_format: Option<String>,
+    /// `field[op]=value` filters, collected from the raw query string.
+    #[serde(skip)]
+    pub filters: Vec<FieldFilter>,
+}
+
+impl QueryParams {
+    /// Whether every result is streamed rather than returned as a page.
+    /// NDJSON is always streamed.
+    pub fn is_streaming(&self) -> bool {
+        self.stream == Some(true) || self.format.as_deref() == Some("ndjson")
+    }
+
+    /// Layout of nested pool rows in CSV exports; unknown layouts are
+    /// rejected by validation.
+    pub fn pools_layout(&self) -> PoolsLayout {
+        self.pools_layout
+            .as_deref()
+            .and_then(PoolsLayout::parse)
+            .unwrap_or_default()
+    }
+
+    /// Shape of JSON and CSV output; unknown profiles and timestamp formats
+    /// are rejected by validation.
+    pub fn output_profile(&self) -> OutputProfile {
+        OutputProfile {
+            version: self
+                .profile
+                .as_deref()
+                .and_then(ProfileVersion::parse)
+                .unwrap_or_default(),
+            timestamps: self
+                .ts_format
+                .as_deref()
+                .and_then(TimestampFormat::parse)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// How the time range selects intervals; unknown modes are rejected by
+    /// validation.
+    pub fn range_mode(&self) -> RangeMode {
+        self.range_mode
+            .as_deref()
+            .and_then(RangeMode::parse)
+            .unwrap_or_default()
+    }
+
+    /// Whether results are restricted to a time range.
+    pub fn has_time_range(&self) -> bool {
+        self.date_range.is_some() || self.from.is_some() || self.to.is_some()
+    }
+
+    /// Time range requested with `date_range` or `from`/`to`, if any.
+    /// Unparsable ranges are rejected by validation and treated as absent.
+    pub fn time_range(&self) -> Option<DateRange> {
+        match &self.date_range {
+            Some(date_range) => parse_date_range(date_range).ok(),
+            None if self.from.is_some() || self.to.is_some() => {
+                parse_bounds(self.from.as_deref(), self.to.as_deref()).ok()
+            }
+            None => None,
+        }
+    }
+
+    /// Fields requested with `fields=`, if any.
+    pub fn selected_fields(&self) -> Option<Vec<&str>> {
+        self.fields.as_deref().map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+                .collect()
+        })
+    }
+}
+
+/// One `field[op]=value` filter as given in the query string.
+#[derive(Debug, Clone)]
+pub struct FieldFilter {
+    pub field: String,
+    pub operator: String,
+    pub value: String,
+}
+
+impl FieldFilter {
+    /// The query parameter the filter was given as, e.g. `totalVolume[gte]`.
+    pub fn param(&self) -> String {
+        format!("{}[{}]", self.field, self.operator)
+    }
+}
+
+/// Format requested by an `Accept` header, for clients that negotiate the
+/// format rather than passing `format=`.
+fn accepted_format(req: &HttpRequest) -> Option<String> {
+    let accept = req.headers().get(header::ACCEPT)?.to_str().ok()?;
+    accept
+        .split(',')
+        .map(|media_type| media_type.split(';').next().unwrap_or_default().trim())
+        .find_map(|media_type| match media_type {
+            "application/x-ndjson" | "application/ndjson" => Some("ndjson".to_string()),
+            "text/csv" => Some("csv".to_string()),
+            "application/vnd.apache.parquet" => Some("parquet".to_string()),
+            "application/vnd.apache.arrow.stream" => Some("arrow".to_string()),
+            _ => None,
+        })
+}
+
+/// Collects every `field[op]=value` pair of a query string.
+fn parse_filters(query_string: &str) -> Vec<FieldFilter> {
+    let Ok(pairs) = web::Query::<Vec<(String, String)>>::from_query(query_string) else {
+        return Vec::new();
+    };
+
+    pairs
+        .into_inner()
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let (field, operator) = key.strip_suffix(']')?.split_once('[')?;
+            Some(FieldFilter {
+                field: field.to_string(),
+                operator: operator.to_string(),
+                value,
+            })
+        })
+        .collect()
+}
+
+impl FromRequest for QueryParams {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let query = web::Query::<QueryParams>::from_request(req, payload);
+        let filters = parse_filters(req.query_string());
+        let accepted = accepted_format(req);
+        Box::pin(async move {
+            let mut params = query.await?.into_inner();
+            params.filters = filters;
+            params.format = params.format.or(accepted);
+            Ok(params)
+        })
+    }
 }
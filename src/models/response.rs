@@ -0,0 +1,108 @@
+use crate::models::{page::Page, query_params::QueryParams};
+use bson::DateTime as BsonDateTime;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Body of every history response, shaped like Midgard's `meta`/`intervals`.
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse<T> {
+    pub meta: ResponseMeta,
+    pub intervals: Vec<T>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseMeta {
+    pub count: usize,
+
+    pub filters: AppliedFilters,
+
+    /// Effective page size after defaults and caps.
+    pub limit: i64,
+
+    /// Stored documents left out of the page because they did not match
+    /// the model.
+    pub skipped: u64,
+
+    #[serde(rename = "hasMore")]
+    pub has_more: bool,
+
+    /// Cursor of the next page, to pass back as `cursor=`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+
+    /// Written in the requested output profile, like the intervals.
+    #[serde(rename = "serverTime")]
+    pub server_time: Value,
+}
+
+/// Query parameters the results were filtered, grouped and ordered by.
+#[derive(Debug, Serialize)]
+pub struct AppliedFilters {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date_range: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_mode: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_by: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ts_format: Option<String>,
+
+    /// `field[op]` filters and their values.
+    #[serde(flatten)]
+    pub values: BTreeMap<String, String>,
+}
+
+impl<T> HistoryResponse<T> {
+    pub fn new(page: Page<T>, params: &QueryParams) -> Self {
+        Self {
+            meta: ResponseMeta {
+                count: page.items.len(),
+                filters: AppliedFilters {
+                    date_range: params.date_range.clone(),
+                    from: params.from.clone(),
+                    to: params.to.clone(),
+                    range_mode: params.range_mode.clone(),
+                    pool: params.pool.clone(),
+                    interval: params.interval.clone(),
+                    sort_by: params.sort_by.clone(),
+                    order: params.order.clone(),
+                    profile: params.profile.clone(),
+                    ts_format: params.ts_format.clone(),
+                    values: params
+                        .filters
+                        .iter()
+                        .map(|filter| (filter.param(), filter.value.clone()))
+                        .collect(),
+                },
+                limit: page.limit,
+                skipped: page.skipped,
+                has_more: page.next.is_some(),
+                next: page.next,
+                server_time: params.output_profile().timestamp(BsonDateTime::now()),
+            },
+            intervals: page.items,
+        }
+    }
+}
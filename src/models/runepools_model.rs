@@ -1,3 +1,4 @@
+use crate::models::field_registry::{Field, FieldRegistry, FieldType};
 use crate::utils::serialization_utils::serialize_datetime_as_timestamp;
 use bson::DateTime as BsonDateTime;
 use serde::{Deserialize, Serialize};
@@ -17,3 +18,12 @@ pub struct RunePool {
 
     pub units: i64,
 }
+
+impl RunePool {
+    pub const FIELDS: FieldRegistry = &[
+        Field::gauge("count", "count", FieldType::Int32),
+        Field::key("endTime", "end_time", FieldType::DateTime),
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::gauge("units", "units", FieldType::Int64),
+    ];
+}
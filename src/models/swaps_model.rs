@@ -1,3 +1,4 @@
+use crate::models::field_registry::{Field, FieldRegistry, FieldType};
 use crate::utils::serialization_utils::serialize_datetime_as_timestamp;
 use bson::DateTime as BsonDateTime;
 use serde::{Deserialize, Serialize};
@@ -15,7 +16,7 @@ pub struct Swaps {
     pub from_trade_average_slip: f64,
 
     #[serde(rename = "fromTradeCount")]
-    pub from_trade_count: i32,
+    pub from_trade_count: i64,
 
     #[serde(rename = "fromTradeFees")]
     pub from_trade_fees: i64,
@@ -26,6 +27,9 @@ pub struct Swaps {
     #[serde(rename = "fromTradeVolumeUSD")]
     pub from_trade_volume_usd: f64,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+
     #[serde(rename = "runePriceUSD")]
     pub rune_price_usd: f64,
 
@@ -37,7 +41,7 @@ pub struct Swaps {
     pub synth_mint_average_slip: f64,
 
     #[serde(rename = "synthMintCount")]
-    pub synth_mint_count: i32,
+    pub synth_mint_count: i64,
 
     #[serde(rename = "synthMintFees")]
     pub synth_mint_fees: i64,
@@ -52,7 +56,7 @@ pub struct Swaps {
     pub synth_redeem_average_slip: f64,
 
     #[serde(rename = "synthRedeemCount")]
-    pub synth_redeem_count: i32,
+    pub synth_redeem_count: i64,
 
     #[serde(rename = "synthRedeemFees")]
     pub synth_redeem_fees: i64,
@@ -67,7 +71,7 @@ pub struct Swaps {
     pub to_asset_average_slip: f64,
 
     #[serde(rename = "toAssetCount")]
-    pub to_asset_count: i32,
+    pub to_asset_count: i64,
 
     #[serde(rename = "toAssetFees")]
     pub to_asset_fees: i64,
@@ -82,7 +86,7 @@ pub struct Swaps {
     pub to_rune_average_slip: f64,
 
     #[serde(rename = "toRuneCount")]
-    pub to_rune_count: i32,
+    pub to_rune_count: i64,
 
     #[serde(rename = "toRuneFees")]
     pub to_rune_fees: i64,
@@ -97,7 +101,7 @@ pub struct Swaps {
     pub to_trade_average_slip: f64,
 
     #[serde(rename = "toTradeCount")]
-    pub to_trade_count: i32,
+    pub to_trade_count: i64,
 
     #[serde(rename = "toTradeFees")]
     pub to_trade_fees: i64,
@@ -109,7 +113,7 @@ pub struct Swaps {
     pub to_trade_volume_usd: f64,
 
     #[serde(rename = "totalCount")]
-    pub total_count: i32,
+    pub total_count: i64,
 
     #[serde(rename = "totalFees")]
     pub total_fees: i64,
@@ -120,3 +124,94 @@ pub struct Swaps {
     #[serde(rename = "totalVolumeUSD")]
     pub total_volume_usd: f64,
 }
+
+impl Swaps {
+    pub const FIELDS: FieldRegistry = &[
+        Field::weighted_average(
+            "averageSlip",
+            "average_slip",
+            FieldType::Double,
+            "totalCount",
+        ),
+        Field::key("endTime", "end_time", FieldType::DateTime),
+        Field::weighted_average(
+            "fromTradeAverageSlip",
+            "from_trade_average_slip",
+            FieldType::Double,
+            "fromTradeCount",
+        ),
+        Field::counter("fromTradeCount", "from_trade_count", FieldType::Int64),
+        Field::counter("fromTradeFees", "from_trade_fees", FieldType::Int64),
+        Field::counter("fromTradeVolume", "from_trade_volume", FieldType::Int64),
+        Field::counter(
+            "fromTradeVolumeUSD",
+            "from_trade_volume_usd",
+            FieldType::Double,
+        ),
+        Field::key("pool", "pool", FieldType::Text),
+        Field::gauge("runePriceUSD", "rune_price_usd", FieldType::Double),
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::weighted_average(
+            "synthMintAverageSlip",
+            "synth_mint_average_slip",
+            FieldType::Double,
+            "synthMintCount",
+        ),
+        Field::counter("synthMintCount", "synth_mint_count", FieldType::Int64),
+        Field::counter("synthMintFees", "synth_mint_fees", FieldType::Int64),
+        Field::counter("synthMintVolume", "synth_mint_volume", FieldType::Int64),
+        Field::counter(
+            "synthMintVolumeUSD",
+            "synth_mint_volume_usd",
+            FieldType::Double,
+        ),
+        Field::weighted_average(
+            "synthRedeemAverageSlip",
+            "synth_redeem_average_slip",
+            FieldType::Double,
+            "synthRedeemCount",
+        ),
+        Field::counter("synthRedeemCount", "synth_redeem_count", FieldType::Int64),
+        Field::counter("synthRedeemFees", "synth_redeem_fees", FieldType::Int64),
+        Field::counter("synthRedeemVolume", "synth_redeem_volume", FieldType::Int64),
+        Field::counter(
+            "synthRedeemVolumeUSD",
+            "synth_redeem_volume_usd",
+            FieldType::Double,
+        ),
+        Field::weighted_average(
+            "toAssetAverageSlip",
+            "to_asset_average_slip",
+            FieldType::Double,
+            "toAssetCount",
+        ),
+        Field::counter("toAssetCount", "to_asset_count", FieldType::Int64),
+        Field::counter("toAssetFees", "to_asset_fees", FieldType::Int64),
+        Field::counter("toAssetVolume", "to_asset_volume", FieldType::Int64),
+        Field::counter("toAssetVolumeUSD", "to_asset_volume_usd", FieldType::Double),
+        Field::weighted_average(
+            "toRuneAverageSlip",
+            "to_rune_average_slip",
+            FieldType::Double,
+            "toRuneCount",
+        ),
+        Field::counter("toRuneCount", "to_rune_count", FieldType::Int64),
+        Field::counter("toRuneFees", "to_rune_fees", FieldType::Int64),
+        Field::counter("toRuneVolume", "to_rune_volume", FieldType::Int64),
+        Field::counter("toRuneVolumeUSD", "to_rune_volume_usd", FieldType::Double),
+        Field::weighted_average(
+            "toTradeAverageSlip",
+            "to_trade_average_slip",
+            FieldType::Double,
+            "toTradeCount",
+        ),
+        Field::counter("toTradeCount", "to_trade_count", FieldType::Int64),
+        Field::counter("toTradeFees", "to_trade_fees", FieldType::Int64),
+        Field::counter("toTradeVolume", "to_trade_volume", FieldType::Int64),
+        Field::counter("toTradeVolumeUSD", "to_trade_volume_usd", FieldType::Double),
+        Field::counter("totalCount", "total_count", FieldType::Int64),
+        Field::counter("totalFees", "total_fees", FieldType::Int64),
+        Field::counter("totalVolume", "total_volume", FieldType::Int64),
+        Field::counter("totalVolumeUSD", "total_volume_usd", FieldType::Double),
+    ];
+}
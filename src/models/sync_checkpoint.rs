@@ -0,0 +1,35 @@
+use crate::models::collection_type::CollectionType;
+use bson::DateTime as BsonDateTime;
+use serde::{Deserialize, Serialize};
+
+/// Ingestion progress for one series, keyed by `SyncCheckpoint::series_id`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncCheckpoint {
+    #[serde(rename = "_id")]
+    pub series: String,
+
+    /// `endTime` of the newest closed interval ingested so far.
+    #[serde(rename = "lastEndTime")]
+    pub last_end_time: BsonDateTime,
+
+    /// `startTime` of the oldest interval ingested so far.
+    #[serde(rename = "firstStartTime")]
+    pub first_start_time: BsonDateTime,
+
+    #[serde(rename = "backfillComplete")]
+    pub backfill_complete: bool,
+
+    #[serde(rename = "updatedAt")]
+    pub updated_at: BsonDateTime,
+}
+
+impl SyncCheckpoint {
+    /// `CollectionType::as_str`, suffixed with the pool for per-pool series
+    /// (e.g. `depths:BTC.BTC`).
+    pub fn series_id(collection_type: CollectionType, pool: Option<&str>) -> String {
+        match pool {
+            Some(pool) => format!("{}:{}", collection_type.as_str(), pool),
+            None => collection_type.as_str().to_string(),
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::handlers::data_handler::get_data;
+use crate::handlers::data_handler::{get_data, get_pool_data, get_pool_earnings};
+use crate::handlers::metrics_handler::get_metrics;
+use crate::handlers::midgard_handler::{get_history, get_pool_history};
 use crate::models::{
     collection_type::CollectionType, depth_model::Depth, earnings_model::Earnings,
     runepools_model::RunePool, swaps_model::Swaps,
@@ -10,19 +12,50 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::scope("/api")
             .route(
                 "/depths",
-                web::get().to(|q, s| get_data::<Depth>(CollectionType::Depths, q, s)),
+                web::get().to(|r, q, s| get_data::<Depth>(CollectionType::Depths, r, q, s)),
+            )
+            .route(
+                "/depths/{pool}",
+                web::get()
+                    .to(|r, p, q, s| get_pool_data::<Depth>(CollectionType::Depths, r, p, q, s)),
             )
             .route(
                 "/swaps",
-                web::get().to(|q, s| get_data::<Swaps>(CollectionType::Swaps, q, s)),
+                web::get().to(|r, q, s| get_data::<Swaps>(CollectionType::Swaps, r, q, s)),
+            )
+            .route(
+                "/swaps/{pool}",
+                web::get()
+                    .to(|r, p, q, s| get_pool_data::<Swaps>(CollectionType::Swaps, r, p, q, s)),
             )
             .route(
                 "/runepools",
-                web::get().to(|q, s| get_data::<RunePool>(CollectionType::Runepools, q, s)),
+                web::get().to(|r, q, s| get_data::<RunePool>(CollectionType::Runepools, r, q, s)),
+            )
+            .route(
+                "/earnings",
+                web::get().to(|r, q, s| get_data::<Earnings>(CollectionType::Earnings, r, q, s)),
+            )
+            .route("/earnings/pools", web::get().to(get_pool_earnings)),
+    )
+    .service(
+        web::scope("/v2/history")
+            .route(
+                "/depths/{pool}",
+                web::get().to(|r, p, q, s| get_pool_history(CollectionType::Depths, r, p, q, s)),
+            )
+            .route(
+                "/swaps",
+                web::get().to(|r, q, s| get_history(CollectionType::Swaps, r, q, s)),
             )
             .route(
                 "/earnings",
-                web::get().to(|q, s| get_data::<Earnings>(CollectionType::Earnings, q, s)),
+                web::get().to(|r, q, s| get_history(CollectionType::Earnings, r, q, s)),
+            )
+            .route(
+                "/runepool",
+                web::get().to(|r, q, s| get_history(CollectionType::Runepools, r, q, s)),
             ),
-    );
+    )
+    .route("/metrics", web::get().to(get_metrics));
 }
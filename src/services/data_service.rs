@@ -1,39 +1,207 @@
 use crate::db::base_db::BaseDB;
-use crate::models::{collection_type::CollectionType, query_params::QueryParams};
+use crate::error::ServiceError;
+use crate::models::{
+    collection_type::CollectionType, earnings_model::PoolEarnings, field_registry::FieldRegistry,
+    page::Page, query_params::QueryParams,
+};
+use crate::services::response_cache::ResponseCache;
+use crate::utils::sort_keys;
+use bson::RawDocumentBuf;
+use futures::stream::BoxStream;
 use mongodb::Database;
 use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+/// What a request reads: a collection's history, or the per-pool rows of
+/// the earnings history.
+#[derive(Debug, Clone, Copy)]
+pub enum Dataset {
+    Collection(CollectionType),
+    PoolEarnings,
+}
+
+impl Dataset {
+    pub fn fields(&self) -> FieldRegistry {
+        match self {
+            Self::Collection(collection_type) => collection_type.fields(),
+            Self::PoolEarnings => PoolEarnings::FIELDS,
+        }
+    }
+
+    /// Collection the dataset is read from.
+    pub fn collection_type(&self) -> CollectionType {
+        match self {
+            Self::Collection(collection_type) => *collection_type,
+            Self::PoolEarnings => CollectionType::Earnings,
+        }
+    }
+
+    /// Whether `pool` is accepted.
+    pub fn is_per_pool(&self) -> bool {
+        match self {
+            Self::Collection(collection_type) => collection_type.is_per_pool(),
+            Self::PoolEarnings => true,
+        }
+    }
+}
 
 pub struct DataService {
     depths_db: BaseDB,
     swaps_db: BaseDB,
     runepools_db: BaseDB,
     earnings_db: BaseDB,
+    cache: Arc<ResponseCache>,
 }
 
 impl DataService {
-    pub fn new(db: &Database) -> Self {
+    pub fn new(db: &Database, cache: Arc<ResponseCache>) -> Self {
         Self {
-            depths_db: BaseDB::new(db, CollectionType::Depths.as_str()),
-            swaps_db: BaseDB::new(db, CollectionType::Swaps.as_str()),
-            runepools_db: BaseDB::new(db, CollectionType::Runepools.as_str()),
-            earnings_db: BaseDB::new(db, CollectionType::Earnings.as_str()),
+            depths_db: BaseDB::new(db, CollectionType::Depths),
+            swaps_db: BaseDB::new(db, CollectionType::Swaps),
+            runepools_db: BaseDB::new(db, CollectionType::Runepools),
+            earnings_db: BaseDB::new(db, CollectionType::Earnings),
+            cache,
         }
     }
 
+    fn collection_db(&self, collection_type: CollectionType) -> &BaseDB {
+        match collection_type {
+            CollectionType::Depths => &self.depths_db,
+            CollectionType::Swaps => &self.swaps_db,
+            CollectionType::Runepools => &self.runepools_db,
+            CollectionType::Earnings => &self.earnings_db,
+        }
+    }
+
+    /// Pools of the earnings rows to return, given as a comma-separated
+    /// `pool=`; empty for all pools.
+    fn earnings_pools(params: &QueryParams) -> Vec<String> {
+        params
+            .pool
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|pool| pool.trim().to_string())
+            .filter(|pool| !pool.is_empty())
+            .collect()
+    }
+
+    /// Identifies the stored documents a validated query reads, whatever
+    /// way its parameters were written: the range as resolved bounds and the
+    /// ordering as sort keys. Output-only parameters are left out.
+    fn cache_key(dataset: Dataset, params: &QueryParams) -> String {
+        let range = params.time_range().map(|range| {
+            (
+                range.start.map(|start| start.timestamp_millis()),
+                range.end.map(|end| end.timestamp_millis()),
+            )
+        });
+        let mut filters: Vec<(&str, &str, &str)> = params
+            .filters
+            .iter()
+            .map(|filter| {
+                (
+                    filter.field.as_str(),
+                    filter.operator.as_str(),
+                    filter.value.as_str(),
+                )
+            })
+            .collect();
+        filters.sort_unstable();
+        format!(
+            "{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}|{:?}",
+            dataset,
+            range,
+            params.range_mode(),
+            sort_keys(&params.sort_by, &params.order),
+            params.limit,
+            params.pool,
+            params.interval,
+            params.cursor,
+            params.selected_fields(),
+            filters
+        )
+    }
+
+    /// One page of stored documents, from the cache when the query was read
+    /// recently and its range is closed, and whether it was read afresh.
+    async fn find_documents(
+        &self,
+        dataset: Dataset,
+        params: &QueryParams,
+    ) -> Result<(Arc<Page<RawDocumentBuf>>, bool), ServiceError> {
+        let collection_type = dataset.collection_type();
+        let cacheable = self
+            .cache
+            .is_cacheable(params.time_range().and_then(|range| range.end));
+        let key = Self::cache_key(dataset, params);
+        if cacheable {
+            if let Some(page) = self.cache.get(collection_type, &key) {
+                return Ok((page, false));
+            }
+        }
+
+        let generation = self.cache.generation(collection_type);
+        let page = match dataset {
+            Dataset::Collection(collection_type) => {
+                self.collection_db(collection_type)
+                    .find_documents(params)
+                    .await?
+            }
+            Dataset::PoolEarnings => {
+                let pools = Self::earnings_pools(params);
+                self.earnings_db
+                    .find_unwound_documents(params, "pools", &pools, PoolEarnings::FIELDS)
+                    .await?
+            }
+        };
+
+        let page = Arc::new(page);
+        if cacheable {
+            self.cache
+                .insert(collection_type, key, page.clone(), generation);
+        }
+        Ok((page, true))
+    }
+
     pub async fn get_data<T>(
         &self,
-        collection_type: CollectionType,
+        dataset: Dataset,
         params: &QueryParams,
-    ) -> Result<Vec<T>, mongodb::error::Error>
+    ) -> Result<Page<T>, ServiceError>
     where
         T: DeserializeOwned,
     {
-        match collection_type {
-            CollectionType::Depths => self.depths_db.find_documents(params),
-            CollectionType::Swaps => self.swaps_db.find_documents(params),
-            CollectionType::Runepools => self.runepools_db.find_documents(params),
-            CollectionType::Earnings => self.earnings_db.find_documents(params),
+        let (page, fresh) = self.find_documents(dataset, params).await?;
+        self.collection_db(dataset.collection_type()).read_page(
+            &page,
+            params,
+            dataset.fields(),
+            fresh,
+        )
+    }
+
+    /// Every result of the query, unpaged, as a stream.
+    pub async fn stream_data<T>(
+        &self,
+        dataset: Dataset,
+        params: &QueryParams,
+    ) -> Result<BoxStream<'static, Result<T, ServiceError>>, ServiceError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        match dataset {
+            Dataset::Collection(collection_type) => {
+                self.collection_db(collection_type)
+                    .stream_documents(params)
+                    .await
+            }
+            Dataset::PoolEarnings => {
+                let pools = Self::earnings_pools(params);
+                self.earnings_db
+                    .stream_unwound_documents(params, "pools", &pools, PoolEarnings::FIELDS)
+                    .await
+            }
         }
-        .await
     }
 }
@@ -0,0 +1,491 @@
+use crate::db::{base_db::BaseDB, sync_state_db::SyncStateDB};
+use crate::error::ServiceError;
+use crate::models::{collection_type::CollectionType, sync_checkpoint::SyncCheckpoint};
+use crate::services::midgard_client::{HistoryQuery, MidgardClient};
+use crate::services::response_cache::ResponseCache;
+use crate::utils::midgard_conversion::interval_to_document;
+use async_trait::async_trait;
+use bson::{DateTime as BsonDateTime, Document};
+use mongodb::Database;
+use std::sync::Arc;
+
+/// Where ingested intervals and sync checkpoints are kept.
+#[async_trait]
+trait SyncStore: Send + Sync {
+    async fn load_checkpoint(&self, series: &str) -> Result<Option<SyncCheckpoint>, ServiceError>;
+
+    async fn save_checkpoint(&self, checkpoint: &SyncCheckpoint) -> Result<(), ServiceError>;
+
+    /// Upserts intervals on the collection's unique key. Returns the number
+    /// of intervals inserted or changed.
+    async fn upsert_intervals(
+        &self,
+        collection_type: CollectionType,
+        documents: Vec<Document>,
+    ) -> Result<u64, ServiceError>;
+}
+
+/// `SyncStore` backed by the MongoDB collections.
+struct MongoSyncStore {
+    sync_state_db: SyncStateDB,
+    depths_db: BaseDB,
+    swaps_db: BaseDB,
+    runepools_db: BaseDB,
+    earnings_db: BaseDB,
+}
+
+impl MongoSyncStore {
+    fn collection_db(&self, collection_type: CollectionType) -> &BaseDB {
+        match collection_type {
+            CollectionType::Depths => &self.depths_db,
+            CollectionType::Swaps => &self.swaps_db,
+            CollectionType::Runepools => &self.runepools_db,
+            CollectionType::Earnings => &self.earnings_db,
+        }
+    }
+}
+
+#[async_trait]
+impl SyncStore for MongoSyncStore {
+    async fn load_checkpoint(&self, series: &str) -> Result<Option<SyncCheckpoint>, ServiceError> {
+        Ok(self.sync_state_db.load(series).await?)
+    }
+
+    async fn save_checkpoint(&self, checkpoint: &SyncCheckpoint) -> Result<(), ServiceError> {
+        Ok(self.sync_state_db.save(checkpoint).await?)
+    }
+
+    async fn upsert_intervals(
+        &self,
+        collection_type: CollectionType,
+        documents: Vec<Document>,
+    ) -> Result<u64, ServiceError> {
+        Ok(self
+            .collection_db(collection_type)
+            .upsert_documents(documents, collection_type.unique_key())
+            .await?)
+    }
+}
+
+/// Bounds of a page of intervals written to a collection.
+struct PageSummary {
+    written: u64,
+    len: usize,
+    first_start: Option<BsonDateTime>,
+    last_closed_end: Option<BsonDateTime>,
+}
+
+pub struct IngestionService {
+    client: MidgardClient,
+    store: Box<dyn SyncStore>,
+    genesis: BsonDateTime,
+    backfill_pages_per_sync: u32,
+    pools: Vec<String>,
+    cache: Arc<ResponseCache>,
+}
+
+impl IngestionService {
+    pub fn new(
+        db: &Database,
+        client: MidgardClient,
+        genesis: BsonDateTime,
+        backfill_pages_per_sync: u32,
+        pools: Vec<String>,
+        cache: Arc<ResponseCache>,
+    ) -> Self {
+        let store = MongoSyncStore {
+            sync_state_db: SyncStateDB::new(db),
+            depths_db: BaseDB::new(db, CollectionType::Depths),
+            swaps_db: BaseDB::new(db, CollectionType::Swaps),
+            runepools_db: BaseDB::new(db, CollectionType::Runepools),
+            earnings_db: BaseDB::new(db, CollectionType::Earnings),
+        };
+        Self {
+            client,
+            store: Box::new(store),
+            genesis,
+            backfill_pages_per_sync,
+            pools,
+            cache,
+        }
+    }
+
+    /// Series synced for a collection: `None` for the aggregate series, and
+    /// one entry per configured pool for collections stored per pool.
+    fn series_pools(&self, collection_type: CollectionType) -> Vec<Option<&str>> {
+        let mut series = Vec::new();
+        if collection_type.has_aggregate_series() {
+            series.push(None);
+        }
+        if collection_type.is_per_pool() {
+            series.extend(self.pools.iter().map(|pool| Some(pool.as_str())));
+        }
+        series
+    }
+
+    /// Fetches one page of history from Midgard and upserts every interval
+    /// that starts at or after the genesis date, tagged with the query's pool.
+    async fn fetch_page(
+        &self,
+        collection_type: CollectionType,
+        query: &HistoryQuery<'_>,
+    ) -> Result<PageSummary, ServiceError> {
+        let intervals = self.client.fetch_history(collection_type, query).await?;
+        let len = intervals.len();
+
+        let mut documents = intervals
+            .into_iter()
+            .map(|interval| interval_to_document(collection_type, interval))
+            .collect::<Result<Vec<Document>, _>>()?;
+        if let Some(pool) = query.pool {
+            for document in &mut documents {
+                document.insert("pool", pool);
+            }
+        }
+        documents.retain(|doc| {
+            doc.get_datetime("startTime")
+                .is_ok_and(|start| *start >= self.genesis)
+        });
+
+        let now = BsonDateTime::now();
+        let first_start = documents
+            .iter()
+            .filter_map(|doc| doc.get_datetime("startTime").ok().copied())
+            .min();
+        // The newest interval is usually still open; it is re-fetched on the
+        // next sync rather than advancing the checkpoint past it.
+        let last_closed_end = documents
+            .iter()
+            .filter_map(|doc| doc.get_datetime("endTime").ok().copied())
+            .filter(|end| *end <= now)
+            .max();
+
+        let written = self
+            .store
+            .upsert_intervals(collection_type, documents)
+            .await?;
+        if written > 0 {
+            self.cache.invalidate(collection_type);
+        }
+
+        Ok(PageSummary {
+            written,
+            len,
+            first_start,
+            last_closed_end,
+        })
+    }
+
+    /// Brings every series of a collection up to date. Returns the number of
+    /// intervals written.
+    pub async fn sync(&self, collection_type: CollectionType) -> Result<u64, ServiceError> {
+        let mut written = 0;
+        for pool in self.series_pools(collection_type) {
+            written += self.sync_series(collection_type, pool).await?;
+        }
+        Ok(written)
+    }
+
+    /// Brings one series up to date: fetches intervals newer than the
+    /// checkpoint, then backfills up to `backfill_pages_per_sync` older pages
+    /// until the genesis date. Returns the number of intervals written.
+    async fn sync_series(
+        &self,
+        collection_type: CollectionType,
+        pool: Option<&str>,
+    ) -> Result<u64, ServiceError> {
+        let series = SyncCheckpoint::series_id(collection_type, pool);
+        let mut checkpoint = self.store.load_checkpoint(&series).await?;
+        let mut written = 0;
+
+        loop {
+            let query = HistoryQuery {
+                pool,
+                from: checkpoint
+                    .as_ref()
+                    .map(|c| c.last_end_time.timestamp_millis() / 1000),
+                to: None,
+            };
+            let page = self.fetch_page(collection_type, &query).await?;
+            written += page.written;
+
+            let advanced = match (&mut checkpoint, page.first_start, page.last_closed_end) {
+                (Some(c), _, Some(end)) if end > c.last_end_time => {
+                    c.last_end_time = end;
+                    true
+                }
+                (None, Some(start), Some(end)) => {
+                    checkpoint = Some(SyncCheckpoint {
+                        series: series.clone(),
+                        last_end_time: end,
+                        first_start_time: start,
+                        backfill_complete: start <= self.genesis,
+                        updated_at: BsonDateTime::now(),
+                    });
+                    true
+                }
+                _ => false,
+            };
+
+            if let Some(c) = checkpoint.as_mut().filter(|_| advanced) {
+                c.updated_at = BsonDateTime::now();
+                self.store.save_checkpoint(c).await?;
+            }
+
+            if !advanced || page.len < MidgardClient::MAX_COUNT {
+                break;
+            }
+        }
+
+        let Some(mut checkpoint) = checkpoint else {
+            return Ok(written);
+        };
+
+        for _ in 0..self.backfill_pages_per_sync {
+            if checkpoint.backfill_complete {
+                break;
+            }
+
+            let query = HistoryQuery {
+                pool,
+                from: None,
+                to: Some(checkpoint.first_start_time.timestamp_millis() / 1000),
+            };
+            let page = self.fetch_page(collection_type, &query).await?;
+            written += page.written;
+
+            match page.first_start {
+                Some(start) if start < checkpoint.first_start_time => {
+                    checkpoint.first_start_time = start;
+                    checkpoint.backfill_complete = start <= self.genesis;
+                }
+                _ => checkpoint.backfill_complete = true,
+            }
+
+            checkpoint.updated_at = BsonDateTime::now();
+            self.store.save_checkpoint(&checkpoint).await?;
+        }
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use super::*;
+    use crate::services::midgard_client::mock::MockMidgard;
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// `SyncStore` kept in memory, shared between its clones. Intervals are
+    /// keyed by their start time, as there is a single series.
+    #[derive(Default, Clone)]
+    pub struct MemoryStore {
+        pub checkpoints: Arc<Mutex<HashMap<String, SyncCheckpoint>>>,
+        pub intervals: Arc<Mutex<BTreeMap<i64, Document>>>,
+    }
+
+    #[async_trait]
+    impl SyncStore for MemoryStore {
+        async fn load_checkpoint(
+            &self,
+            series: &str,
+        ) -> Result<Option<SyncCheckpoint>, ServiceError> {
+            Ok(self.checkpoints.lock().unwrap().get(series).cloned())
+        }
+
+        async fn save_checkpoint(&self, checkpoint: &SyncCheckpoint) -> Result<(), ServiceError> {
+            self.checkpoints
+                .lock()
+                .unwrap()
+                .insert(checkpoint.series.clone(), checkpoint.clone());
+            Ok(())
+        }
+
+        async fn upsert_intervals(
+            &self,
+            _collection_type: CollectionType,
+            documents: Vec<Document>,
+        ) -> Result<u64, ServiceError> {
+            let mut intervals = self.intervals.lock().unwrap();
+            let mut written = 0;
+            for document in documents {
+                let start = document
+                    .get_datetime("startTime")
+                    .unwrap()
+                    .timestamp_millis();
+                if intervals.get(&start) != Some(&document) {
+                    intervals.insert(start, document);
+                    written += 1;
+                }
+            }
+            Ok(written)
+        }
+    }
+
+    /// Service syncing from `midgard` into `store`, with history starting
+    /// at `genesis` (unix seconds).
+    pub fn service(midgard: &MockMidgard, store: &MemoryStore, genesis: i64) -> IngestionService {
+        IngestionService {
+            client: MidgardClient::new(&midgard.url, "hour").unwrap(),
+            store: Box::new(store.clone()),
+            genesis: BsonDateTime::from_millis(genesis * 1000),
+            backfill_pages_per_sync: 5,
+            pools: Vec::new(),
+            cache: Arc::new(ResponseCache::new(0, Duration::ZERO, Duration::ZERO)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{service, MemoryStore};
+    use super::*;
+    use crate::services::midgard_client::mock::{interval, MockMidgard};
+    use chrono::Utc;
+
+    const HOUR: i64 = 3600;
+    const SERIES: CollectionType = CollectionType::Runepools;
+
+    impl MemoryStore {
+        fn checkpoint(&self) -> Option<SyncCheckpoint> {
+            let series = SyncCheckpoint::series_id(SERIES, None);
+            self.checkpoints.lock().unwrap().get(&series).cloned()
+        }
+    }
+
+    fn time(secs: i64) -> BsonDateTime {
+        BsonDateTime::from_millis(secs * 1000)
+    }
+
+    /// Start times of `len` hourly intervals, the last of which started a
+    /// minute ago and so is still open.
+    fn timeline(len: i64) -> Vec<i64> {
+        let open_start = Utc::now().timestamp() - 60;
+        (0..len)
+            .map(|i| open_start - (len - 1 - i) * HOUR)
+            .collect()
+    }
+
+    fn midgard(starts: &[i64]) -> MockMidgard {
+        MockMidgard::start(
+            starts
+                .iter()
+                .map(|start| interval(*start, start + HOUR))
+                .collect(),
+        )
+    }
+
+    fn checkpoint(last_end: i64, first_start: i64) -> SyncCheckpoint {
+        SyncCheckpoint {
+            series: SyncCheckpoint::series_id(SERIES, None),
+            last_end_time: time(last_end),
+            first_start_time: time(first_start),
+            backfill_complete: true,
+            updated_at: time(0),
+        }
+    }
+
+    fn param(midgard: &MockMidgard, name: &str) -> Vec<Option<String>> {
+        midgard
+            .requests()
+            .into_iter()
+            .map(|request| request.query.get(name).cloned())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn first_sync_reads_the_latest_page() {
+        let starts = timeline(10);
+        let midgard = midgard(&starts);
+        let store = MemoryStore::default();
+
+        let written = service(&midgard, &store, starts[0])
+            .sync(SERIES)
+            .await
+            .unwrap();
+
+        assert_eq!(written, 10);
+        assert_eq!(param(&midgard, "count"), [Some("400".to_string())]);
+        assert_eq!(param(&midgard, "from"), [None]);
+        let checkpoint = store.checkpoint().unwrap();
+        assert_eq!(checkpoint.first_start_time, time(starts[0]));
+        assert_eq!(checkpoint.last_end_time, time(starts[9]));
+        assert!(checkpoint.backfill_complete);
+    }
+
+    #[actix_web::test]
+    async fn catches_up_page_by_page_while_pages_are_full() {
+        let starts = timeline(1000);
+        let midgard = midgard(&starts);
+        let store = MemoryStore::default();
+        store
+            .save_checkpoint(&checkpoint(starts[100], starts[0]))
+            .await
+            .unwrap();
+
+        let written = service(&midgard, &store, starts[0])
+            .sync(SERIES)
+            .await
+            .unwrap();
+
+        assert_eq!(written, 900);
+        let froms: Vec<Option<String>> = [100, 500, 900]
+            .iter()
+            .map(|i| Some(starts[*i].to_string()))
+            .collect();
+        assert_eq!(param(&midgard, "from"), froms);
+        assert_eq!(store.checkpoint().unwrap().last_end_time, time(starts[999]));
+    }
+
+    #[actix_web::test]
+    async fn does_not_advance_past_the_open_interval() {
+        let starts = timeline(5);
+        let midgard = midgard(&starts);
+        let store = MemoryStore::default();
+        store
+            .save_checkpoint(&checkpoint(starts[4], starts[0]))
+            .await
+            .unwrap();
+        let service = service(&midgard, &store, starts[0]);
+
+        // The open interval is stored, but fetched again on the next sync.
+        assert_eq!(service.sync(SERIES).await.unwrap(), 1);
+        assert_eq!(service.sync(SERIES).await.unwrap(), 0);
+
+        let from = Some(starts[4].to_string());
+        assert_eq!(param(&midgard, "from"), [from.clone(), from]);
+        let checkpoint = store.checkpoint().unwrap();
+        assert_eq!(checkpoint.last_end_time, time(starts[4]));
+        assert_eq!(checkpoint.updated_at, time(0));
+    }
+
+    #[actix_web::test]
+    async fn backfills_older_pages_until_genesis() {
+        // Midgard's first 50 intervals predate the configured genesis.
+        let starts = timeline(1050);
+        let midgard = midgard(&starts);
+        let store = MemoryStore::default();
+
+        let written = service(&midgard, &store, starts[50])
+            .sync(SERIES)
+            .await
+            .unwrap();
+
+        assert_eq!(written, 1000);
+        // The first page is full, so the latest intervals are read once more
+        // from the open one before backfilling.
+        let bound = |i: Option<usize>| i.map(|i| starts[i].to_string());
+        let froms: Vec<_> = [None, Some(1049), None, None].map(bound).into();
+        let tos: Vec<_> = [None, None, Some(650), Some(250)].map(bound).into();
+        assert_eq!(param(&midgard, "from"), froms);
+        assert_eq!(param(&midgard, "to"), tos);
+
+        let checkpoint = store.checkpoint().unwrap();
+        assert_eq!(checkpoint.first_start_time, time(starts[50]));
+        assert!(checkpoint.backfill_complete);
+        let intervals = store.intervals.lock().unwrap();
+        assert_eq!(intervals.keys().next(), Some(&(starts[50] * 1000)));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::error::ServiceError;
+use crate::models::collection_type::CollectionType;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+struct HistoryResponse {
+    intervals: Vec<Map<String, Value>>,
+}
+
+/// Series and window of a Midgard history request, as unix seconds.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HistoryQuery<'a> {
+    pub pool: Option<&'a str>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+pub struct MidgardClient {
+    http: reqwest::Client,
+    base_url: String,
+    interval: String,
+}
+
+impl MidgardClient {
+    pub const MAX_COUNT: usize = 400;
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub fn new(base_url: &str, interval: &str) -> Result<Self, ServiceError> {
+        let http = reqwest::Client::builder()
+            .timeout(Self::REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            interval: interval.to_string(),
+        })
+    }
+
+    /// Depth history only exists per pool, so for depths the pool is part of
+    /// the path rather than a query parameter.
+    fn history_url(&self, collection_type: CollectionType, pool: Option<&str>) -> String {
+        match collection_type {
+            CollectionType::Depths => format!(
+                "{}/v2/history/depths/{}",
+                self.base_url,
+                pool.unwrap_or_default()
+            ),
+            CollectionType::Swaps => format!("{}/v2/history/swaps", self.base_url),
+            CollectionType::Runepools => format!("{}/v2/history/runepool", self.base_url),
+            CollectionType::Earnings => format!("{}/v2/history/earnings", self.base_url),
+        }
+    }
+
+    pub async fn fetch_history(
+        &self,
+        collection_type: CollectionType,
+        query: &HistoryQuery<'_>,
+    ) -> Result<Vec<Map<String, Value>>, ServiceError> {
+        let mut params = vec![("interval", self.interval.clone())];
+
+        match (collection_type, query.pool) {
+            (CollectionType::Depths, _) | (_, None) => {}
+            (_, Some(pool)) => params.push(("pool", pool.to_string())),
+        }
+
+        // Midgard rejects `count` when both ends of the window are given.
+        if query.from.is_none() || query.to.is_none() {
+            params.push(("count", Self::MAX_COUNT.to_string()));
+        }
+        if let Some(from) = query.from {
+            params.push(("from", from.to_string()));
+        }
+        if let Some(to) = query.to {
+            params.push(("to", to.to_string()));
+        }
+
+        let response = self
+            .http
+            .get(self.history_url(collection_type, query.pool))
+            .query(&params)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let body: HistoryResponse = response.json().await?;
+        Ok(body.intervals)
+    }
+}
+
+/// A local HTTP server answering Midgard's history routes from a fixed list
+/// of intervals, paged by `from`, `to` and `count` the way Midgard does.
+#[cfg(test)]
+pub(crate) mod mock {
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::{json, Map, Value};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Path and query parameters of a request received by the mock.
+    #[derive(Debug, Clone)]
+    pub struct Request {
+        pub path: String,
+        pub query: HashMap<String, String>,
+    }
+
+    pub struct MockMidgard {
+        pub url: String,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    /// An interval as Midgard sends it, with unix-second bounds as strings.
+    pub fn interval(start: i64, end: i64) -> Map<String, Value> {
+        let Value::Object(interval) = json!({
+            "startTime": start.to_string(),
+            "endTime": end.to_string(),
+        }) else {
+            unreachable!()
+        };
+        interval
+    }
+
+    fn bound(interval: &Map<String, Value>, name: &str) -> i64 {
+        interval[name].as_str().unwrap().parse().unwrap()
+    }
+
+    /// Intervals overlapping `from`..`to`: the first `count` after `from`, or
+    /// the last `count` when only `to` (or neither) is given.
+    fn page(intervals: &[Map<String, Value>], query: &HashMap<String, String>) -> Vec<Value> {
+        let param = |name: &str| query.get(name).map(|value| value.parse::<i64>().unwrap());
+        let (from, to, count) = (param("from"), param("to"), param("count"));
+        let selected: Vec<&Map<String, Value>> = intervals
+            .iter()
+            .filter(|interval| from.is_none_or(|from| bound(interval, "endTime") > from))
+            .filter(|interval| to.is_none_or(|to| bound(interval, "startTime") < to))
+            .collect();
+        let count = count.map_or(selected.len(), |count| count as usize);
+        let page = if from.is_some() {
+            &selected[..count.min(selected.len())]
+        } else {
+            &selected[selected.len().saturating_sub(count)..]
+        };
+        page.iter()
+            .map(|interval| Value::Object((*interval).clone()))
+            .collect()
+    }
+
+    impl MockMidgard {
+        /// Starts serving `intervals`, in time order, on a free local port.
+        /// Must be called from within an actix runtime.
+        pub fn start(intervals: Vec<Map<String, Value>>) -> Self {
+            let intervals = Arc::new(intervals);
+            let requests = Arc::new(Mutex::new(Vec::new()));
+            let received = requests.clone();
+            let server = HttpServer::new(move || {
+                let intervals = intervals.clone();
+                let received = received.clone();
+                App::new().default_service(web::to(
+                    move |request: HttpRequest, query: web::Query<HashMap<String, String>>| {
+                        let body = json!({ "intervals": page(&intervals, &query), "meta": {} });
+                        received.lock().unwrap().push(Request {
+                            path: request.path().to_string(),
+                            query: query.into_inner(),
+                        });
+                        async move { HttpResponse::Ok().json(body) }
+                    },
+                ))
+            })
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .expect("bind mock Midgard");
+            let url = format!("http://{}", server.addrs()[0]);
+            actix_web::rt::spawn(server.run());
+            Self { url, requests }
+        }
+
+        /// Requests received so far, oldest first.
+        pub fn requests(&self) -> Vec<Request> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{interval, MockMidgard};
+    use super::*;
+
+    #[actix_web::test]
+    async fn fetches_history_pages_from_midgard() {
+        let intervals: Vec<_> = (0..500)
+            .map(|i| interval(3600 * i, 3600 * (i + 1)))
+            .collect();
+        let midgard = MockMidgard::start(intervals.clone());
+        let client = MidgardClient::new(&format!("{}/", midgard.url), "hour").unwrap();
+
+        let query = HistoryQuery {
+            pool: Some("BTC.BTC"),
+            from: Some(3600 * 50),
+            to: None,
+        };
+        let page = client
+            .fetch_history(CollectionType::Swaps, &query)
+            .await
+            .unwrap();
+        assert_eq!(page.len(), MidgardClient::MAX_COUNT);
+        assert_eq!(page[0], intervals[50]);
+
+        let query = HistoryQuery {
+            pool: Some("BTC.BTC"),
+            from: Some(0),
+            to: Some(3600 * 10),
+        };
+        let page = client
+            .fetch_history(CollectionType::Depths, &query)
+            .await
+            .unwrap();
+        assert_eq!(page, intervals[..10]);
+
+        let requests = midgard.requests();
+        assert_eq!(requests[0].path, "/v2/history/swaps");
+        assert_eq!(requests[0].query["pool"], "BTC.BTC");
+        assert_eq!(requests[0].query["interval"], "hour");
+        assert_eq!(requests[0].query["count"], "400");
+        assert_eq!(requests[0].query["from"], "180000");
+
+        // Depths take the pool in the path, and `count` is left out when
+        // both ends of the window are given.
+        assert_eq!(requests[1].path, "/v2/history/depths/BTC.BTC");
+        assert!(!requests[1].query.contains_key("pool"));
+        assert!(!requests[1].query.contains_key("count"));
+    }
+
+    #[actix_web::test]
+    async fn reports_unreachable_midgard_as_upstream_errors() {
+        let client = MidgardClient::new("http://127.0.0.1:1", "hour").unwrap();
+        let error = client
+            .fetch_history(CollectionType::Earnings, &HistoryQuery::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), "UPSTREAM_ERROR");
+    }
+}
@@ -1 +1,5 @@
 pub mod data_service;
+pub mod ingestion_service;
+pub mod midgard_client;
+pub mod response_cache;
+pub mod sync_worker;
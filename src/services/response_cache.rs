@@ -0,0 +1,163 @@
+use crate::models::{collection_type::CollectionType, page::Page};
+use bson::RawDocumentBuf;
+use chrono::{DateTime, Utc};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A cached page and when it was read.
+struct Entry {
+    page: Arc<Page<RawDocumentBuf>>,
+    read_at: Instant,
+}
+
+/// Pages of stored documents read for recent queries, one least recently
+/// used cache per collection in `CollectionType::ALL` order. Entries expire
+/// after `ttl`, and a collection's entries are dropped whenever new data is
+/// written to it.
+pub struct ResponseCache {
+    collections: Option<[Mutex<LruCache<String, Entry>>; 4]>,
+    /// Bumped by every `invalidate`, so that pages read before new data was
+    /// written are not cached after it.
+    generations: [AtomicU64; 4],
+    ttl: Duration,
+    open_interval: Duration,
+}
+
+impl ResponseCache {
+    /// Keeps up to `capacity` pages per collection; a capacity of zero
+    /// disables caching. `open_interval` is the length of a stored interval,
+    /// see `is_cacheable`.
+    pub fn new(capacity: usize, ttl: Duration, open_interval: Duration) -> Self {
+        Self {
+            collections: NonZeroUsize::new(capacity)
+                .map(|capacity| std::array::from_fn(|_| Mutex::new(LruCache::new(capacity)))),
+            generations: std::array::from_fn(|_| AtomicU64::new(0)),
+            ttl,
+            open_interval,
+        }
+    }
+
+    fn collection(
+        &self,
+        collection_type: CollectionType,
+    ) -> Option<&Mutex<LruCache<String, Entry>>> {
+        self.collections
+            .as_ref()
+            .map(|collections| &collections[collection_type as usize])
+    }
+
+    /// Whether results for a range ending at `end` can be cached. Ranges
+    /// without an end, or ending within one stored interval of now, may
+    /// include the interval still being filled and are always read afresh.
+    pub fn is_cacheable(&self, end: Option<DateTime<Utc>>) -> bool {
+        let open_interval = chrono::Duration::from_std(self.open_interval).unwrap_or_default();
+        end.is_some_and(|end| end <= Utc::now() - open_interval)
+    }
+
+    pub fn get(
+        &self,
+        collection_type: CollectionType,
+        key: &str,
+    ) -> Option<Arc<Page<RawDocumentBuf>>> {
+        let mut cache = self.collection(collection_type)?.lock().ok()?;
+        match cache.get(key) {
+            Some(entry) if entry.read_at.elapsed() < self.ttl => Some(entry.page.clone()),
+            Some(_) => {
+                cache.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Current generation of a collection, to be taken before reading a
+    /// page that is then passed to `insert`.
+    pub fn generation(&self, collection_type: CollectionType) -> u64 {
+        self.generations[collection_type as usize].load(Ordering::SeqCst)
+    }
+
+    /// Caches a page read while the collection was at `generation`; pages
+    /// read before the latest `invalidate` are dropped.
+    pub fn insert(
+        &self,
+        collection_type: CollectionType,
+        key: String,
+        page: Arc<Page<RawDocumentBuf>>,
+        generation: u64,
+    ) {
+        let Some(Ok(mut cache)) = self.collection(collection_type).map(Mutex::lock) else {
+            return;
+        };
+        // Checked under the lock: an `invalidate` that bumped the generation
+        // after this check clears the page once it gets the lock.
+        if self.generation(collection_type) != generation {
+            return;
+        }
+        cache.put(
+            key,
+            Entry {
+                page,
+                read_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops every cached page of a collection, after new data was written
+    /// to it.
+    pub fn invalidate(&self, collection_type: CollectionType) {
+        self.generations[collection_type as usize].fetch_add(1, Ordering::SeqCst);
+        if let Some(Ok(mut cache)) = self.collection(collection_type).map(Mutex::lock) {
+            cache.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> Arc<Page<RawDocumentBuf>> {
+        Arc::new(Page {
+            items: Vec::new(),
+            next: None,
+            limit: 24,
+            skipped: 0,
+        })
+    }
+
+    fn cache() -> ResponseCache {
+        ResponseCache::new(4, Duration::from_secs(60), Duration::from_secs(3600))
+    }
+
+    #[test]
+    fn serves_inserted_pages_until_invalidated() {
+        let cache = cache();
+        let generation = cache.generation(CollectionType::Swaps);
+        cache.insert(CollectionType::Swaps, "key".to_string(), page(), generation);
+        assert!(cache.get(CollectionType::Swaps, "key").is_some());
+        assert!(cache.get(CollectionType::Depths, "key").is_none());
+
+        cache.invalidate(CollectionType::Swaps);
+        assert!(cache.get(CollectionType::Swaps, "key").is_none());
+    }
+
+    #[test]
+    fn drops_pages_read_before_an_invalidation() {
+        let cache = cache();
+        let generation = cache.generation(CollectionType::Swaps);
+        cache.invalidate(CollectionType::Swaps);
+        cache.insert(CollectionType::Swaps, "key".to_string(), page(), generation);
+        assert!(cache.get(CollectionType::Swaps, "key").is_none());
+    }
+
+    #[test]
+    fn only_caches_ranges_ending_before_the_open_interval() {
+        let cache = cache();
+        assert!(!cache.is_cacheable(None));
+        assert!(!cache.is_cacheable(Some(Utc::now())));
+        assert!(cache.is_cacheable(Some(Utc::now() - chrono::Duration::hours(2))));
+    }
+}
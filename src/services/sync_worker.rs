@@ -0,0 +1,170 @@
+use crate::models::collection_type::CollectionType;
+use crate::services::ingestion_service::IngestionService;
+use actix_web::web;
+use log::{info, warn};
+use rand::Rng;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// Background task that keeps every collection in sync with Midgard, each on
+/// its own schedule.
+pub struct SyncWorker {
+    shutdown: watch::Sender<bool>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl SyncWorker {
+    const INITIAL_BACKOFF: Duration = Duration::from_secs(30);
+    const JITTER_FRACTION: f64 = 0.1;
+
+    pub fn start(
+        service: web::Data<IngestionService>,
+        schedule: Vec<(CollectionType, Duration)>,
+    ) -> Self {
+        let (shutdown, shutdown_rx) = watch::channel(false);
+
+        let handles = schedule
+            .into_iter()
+            .map(|(collection_type, interval)| {
+                tokio::spawn(Self::run(
+                    service.clone(),
+                    collection_type,
+                    interval,
+                    shutdown_rx.clone(),
+                ))
+            })
+            .collect();
+
+        Self { shutdown, handles }
+    }
+
+    async fn run(
+        service: web::Data<IngestionService>,
+        collection_type: CollectionType,
+        interval: Duration,
+        mut shutdown: watch::Receiver<bool>,
+    ) {
+        let mut failures: u32 = 0;
+
+        loop {
+            let result = tokio::select! {
+                result = service.sync(collection_type) => result,
+                _ = shutdown.changed() => break,
+            };
+
+            failures = match &result {
+                Ok(_) => 0,
+                Err(_) => failures.saturating_add(1),
+            };
+            let delay = Self::next_delay(failures, interval, rand::thread_rng().gen());
+            match result {
+                Ok(written) => {
+                    info!(
+                        "Synced {} intervals into {}",
+                        written,
+                        collection_type.as_str()
+                    )
+                }
+                Err(e) => warn!(
+                    "Failed to sync {}, retrying in {:?}: {}",
+                    collection_type.as_str(),
+                    delay,
+                    e
+                ),
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        info!("Stopped sync worker for {}", collection_type.as_str());
+    }
+
+    /// Delay before the next sync after `failures` consecutive failed ones:
+    /// `interval` after a success, otherwise `INITIAL_BACKOFF` doubling with
+    /// each further failure, capped at `interval`. `jitter`, between 0 and 1,
+    /// adds up to `JITTER_FRACTION` of the delay so collections do not sync in
+    /// lockstep.
+    fn next_delay(failures: u32, interval: Duration, jitter: f64) -> Duration {
+        let delay = match failures {
+            0 => interval,
+            n => Self::INITIAL_BACKOFF
+                .saturating_mul(2u32.saturating_pow(n - 1))
+                .min(interval),
+        };
+        delay + delay.mul_f64(jitter.clamp(0.0, 1.0) * Self::JITTER_FRACTION)
+    }
+
+    /// Signals every collection task to stop and waits for them to finish.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for handle in self.handles {
+            if let Err(e) = handle.await {
+                warn!("Sync worker task failed: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ingestion_service::mock::{service, MemoryStore};
+    use crate::services::midgard_client::mock::MockMidgard;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn backoff_starts_at_30s_and_doubles_up_to_the_interval() {
+        let delays: Vec<Duration> = (1..=9)
+            .map(|failures| SyncWorker::next_delay(failures, secs(600), 0.0))
+            .collect();
+        assert_eq!(
+            delays,
+            [30, 60, 120, 240, 480, 600, 600, 600, 600].map(secs)
+        );
+
+        assert_eq!(SyncWorker::next_delay(u32::MAX, HOUR, 0.0), HOUR);
+        assert_eq!(SyncWorker::next_delay(1, secs(10), 0.0), secs(10));
+    }
+
+    #[test]
+    fn a_successful_sync_waits_the_interval() {
+        assert_eq!(SyncWorker::next_delay(0, HOUR, 0.0), HOUR);
+    }
+
+    #[test]
+    fn jitter_adds_up_to_a_tenth() {
+        assert_eq!(SyncWorker::next_delay(1, HOUR, 1.0), secs(33));
+        assert_eq!(
+            SyncWorker::next_delay(1, HOUR, 0.5),
+            Duration::from_millis(31_500)
+        );
+        assert_eq!(SyncWorker::next_delay(0, HOUR, 1.0), secs(3960));
+        assert_eq!(SyncWorker::next_delay(1, HOUR, 7.0), secs(33));
+        assert_eq!(SyncWorker::next_delay(1, HOUR, -1.0), secs(30));
+    }
+
+    #[actix_web::test]
+    async fn stops_when_shutdown_is_signalled() {
+        let midgard = MockMidgard::start(Vec::new());
+        let service = web::Data::new(service(&midgard, &MemoryStore::default(), 0));
+        let worker = SyncWorker::start(service, vec![(CollectionType::Runepools, HOUR)]);
+
+        // Let the first sync run, so the task is waiting out the interval.
+        while midgard.requests().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        tokio::time::timeout(secs(5), worker.shutdown())
+            .await
+            .expect("the worker stops without waiting for its next sync");
+    }
+}
@@ -1,22 +1,54 @@
 use crate::utils::config::AppError;
 use crate::{db, services};
 use actix_web::web;
+use std::sync::Arc;
 
 pub struct AppState {
     pub data_service: web::Data<services::data_service::DataService>,
+    pub ingestion_service: web::Data<services::ingestion_service::IngestionService>,
 }
 
 impl AppState {
     pub async fn new(config: &crate::utils::config::AppConfig) -> Result<Self, AppError> {
-        let db = db::init_db(&config.mongodb_uri, &config.database_name)
-            .await
-            .map_err(|e| AppError(format!("Database initialization failed: {}", e)))?;
+        let db = db::init_db(
+            &config.mongodb_uri,
+            &config.database_name,
+            config.remove_duplicate_intervals,
+        )
+        .await
+        .map_err(|e| AppError(format!("Database initialization failed: {}", e)))?;
 
         log::info!("Connected to MongoDB at {}", config.mongodb_uri);
 
+        // Shared so that ingestion can drop pages of collections it writes to
+        let cache = Arc::new(services::response_cache::ResponseCache::new(
+            config.cache_capacity,
+            config.cache_ttl,
+            config.midgard_interval_length(),
+        ));
+
         // Create DataService with database reference
-        let data_service = web::Data::new(services::data_service::DataService::new(&db));
+        let data_service =
+            web::Data::new(services::data_service::DataService::new(&db, cache.clone()));
+
+        let midgard_client = services::midgard_client::MidgardClient::new(
+            &config.midgard_base_url,
+            &config.midgard_interval,
+        )
+        .map_err(|e| AppError(format!("Midgard client initialization failed: {}", e)))?;
+
+        let ingestion_service = web::Data::new(services::ingestion_service::IngestionService::new(
+            &db,
+            midgard_client,
+            bson::DateTime::from_chrono(config.midgard_genesis),
+            config.backfill_pages_per_sync,
+            config.midgard_pools.clone(),
+            cache,
+        ));
 
-        Ok(Self { data_service })
+        Ok(Self {
+            data_service,
+            ingestion_service,
+        })
     }
 }
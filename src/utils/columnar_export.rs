@@ -0,0 +1,401 @@
+// utils/columnar_export.rs
+use crate::models::field_registry::{resolve_field, Field, FieldRegistry, FieldType};
+use arrow_array::{
+    ArrayRef, Decimal128Array, Float64Array, Int32Array, Int64Array, ListArray, RecordBatch,
+    StringArray, StructArray, TimestampMillisecondArray,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer, ScalarBuffer};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{
+    ArrowError, DataType, Field as ArrowField, Fields, Schema, SchemaRef, TimeUnit,
+};
+use bson::{Bson, Document};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use std::sync::Arc;
+
+/// Precision and scale of decimal columns. Midgard's decimal amounts are
+/// integers in 1e-8 units, which 38 digits hold with room to spare.
+const DECIMAL_PRECISION: u8 = 38;
+const DECIMAL_SCALE: i8 = 0;
+
+fn data_type(field_type: FieldType) -> DataType {
+    match field_type {
+        FieldType::DateTime => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        FieldType::Decimal => DataType::Decimal128(DECIMAL_PRECISION, DECIMAL_SCALE),
+        FieldType::Double => DataType::Float64,
+        FieldType::Int32 => DataType::Int32,
+        FieldType::Int64 => DataType::Int64,
+        FieldType::Text | FieldType::NumericText => DataType::Utf8,
+        FieldType::Array(rows) => DataType::List(Arc::new(ArrowField::new(
+            "item",
+            DataType::Struct(struct_fields(rows)),
+            true,
+        ))),
+    }
+}
+
+fn struct_fields(registry: FieldRegistry) -> Fields {
+    registry
+        .iter()
+        .map(|field| ArrowField::new(field.name, data_type(field.field_type), true))
+        .collect()
+}
+
+/// Schema of an export of `registry`, limited to the `fields=` selection
+/// when there is one, with the fields its columns are read from.
+pub fn schema(
+    registry: FieldRegistry,
+    selected: Option<&[String]>,
+) -> (SchemaRef, Vec<&'static Field>) {
+    let fields: Vec<&'static Field> = match selected {
+        Some(selected) => selected
+            .iter()
+            .filter_map(|name| resolve_field(registry, name))
+            .collect(),
+        None => registry.iter().collect(),
+    };
+    let schema = Schema::new(
+        fields
+            .iter()
+            .map(|field| ArrowField::new(field.name, data_type(field.field_type), true))
+            .collect::<Vec<_>>(),
+    );
+    (Arc::new(schema), fields)
+}
+
+/// Value of a decimal in units of `10^-DECIMAL_SCALE`, if it has no finer
+/// digits. Accepts the plain and exponent forms `Decimal128` prints.
+fn decimal_units(text: &str) -> Option<i128> {
+    let (mantissa, exponent) = match text.split_once(['E', 'e']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (text, 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits: i128 = format!("{}{}", whole, fraction).parse().ok()?;
+    let mut shift = exponent - fraction.len() as i32 + i32::from(DECIMAL_SCALE);
+
+    let mut value = digits;
+    while shift > 0 {
+        value = value.checked_mul(10)?;
+        shift -= 1;
+    }
+    while shift < 0 {
+        if value % 10 != 0 {
+            return None;
+        }
+        value /= 10;
+        shift += 1;
+    }
+    Some(value)
+}
+
+/// Converts the value of `path` in each document with `convert`. Missing
+/// and null values become nulls; values `convert` rejects, such as a decimal
+/// with fraction digits or an Int64 in an Int32 column, are an error rather
+/// than silently exported as nulls.
+fn convert<'a, T>(
+    path: &str,
+    field_type: FieldType,
+    values: &[Option<&'a Bson>],
+    convert: impl Fn(&'a Bson) -> Option<T>,
+) -> Result<Vec<Option<T>>, ArrowError> {
+    values
+        .iter()
+        .map(|value| match value {
+            None | Some(Bson::Null) => Ok(None),
+            Some(value) => convert(value).map(Some).ok_or_else(|| {
+                ArrowError::InvalidArgumentError(format!(
+                    "{} value {} does not fit its {} column",
+                    path,
+                    value,
+                    data_type(field_type)
+                ))
+            }),
+        })
+        .collect()
+}
+
+/// Builds the column of field `path` from its value in each document.
+fn build_column(
+    path: &str,
+    field_type: FieldType,
+    values: &[Option<&Bson>],
+) -> Result<ArrayRef, ArrowError> {
+    let column: ArrayRef = match field_type {
+        FieldType::DateTime => {
+            let values = convert(path, field_type, values, |value| {
+                value.as_datetime().map(|d| d.timestamp_millis())
+            })?;
+            Arc::new(TimestampMillisecondArray::from(values).with_timezone("UTC"))
+        }
+        FieldType::Decimal => {
+            let values = convert(path, field_type, values, |value| match value {
+                Bson::Decimal128(decimal) => decimal_units(&decimal.to_string()),
+                _ => None,
+            })?;
+            Arc::new(
+                Decimal128Array::from(values)
+                    .with_precision_and_scale(DECIMAL_PRECISION, DECIMAL_SCALE)?,
+            )
+        }
+        FieldType::Double => Arc::new(Float64Array::from(convert(
+            path,
+            field_type,
+            values,
+            |value| match value {
+                Bson::Double(v) => Some(*v),
+                Bson::Int32(v) => Some(f64::from(*v)),
+                Bson::Int64(v) => Some(*v as f64),
+                _ => None,
+            },
+        )?)),
+        FieldType::Int32 => Arc::new(Int32Array::from(convert(
+            path,
+            field_type,
+            values,
+            Bson::as_i32,
+        )?)),
+        FieldType::Int64 => Arc::new(Int64Array::from(convert(
+            path,
+            field_type,
+            values,
+            |value| match value {
+                Bson::Int64(v) => Some(*v),
+                Bson::Int32(v) => Some(i64::from(*v)),
+                _ => None,
+            },
+        )?)),
+        FieldType::Text | FieldType::NumericText => Arc::new(StringArray::from(convert(
+            path,
+            field_type,
+            values,
+            Bson::as_str,
+        )?)),
+        FieldType::Array(registry) => build_list_column(path, registry, values)?,
+    };
+    Ok(column)
+}
+
+/// Builds a list-of-structs column from arrays of row documents.
+fn build_list_column(
+    path: &str,
+    registry: FieldRegistry,
+    values: &[Option<&Bson>],
+) -> Result<ArrayRef, ArrowError> {
+    let arrays = convert(path, FieldType::Array(registry), values, |value| {
+        let items = value.as_array()?;
+        items
+            .iter()
+            .map(Bson::as_document)
+            .collect::<Option<Vec<_>>>()
+    })?;
+
+    let mut offsets = vec![0i32];
+    let mut valid = Vec::with_capacity(arrays.len());
+    let mut rows: Vec<&Document> = Vec::new();
+    for array in arrays {
+        valid.push(array.is_some());
+        rows.extend(array.unwrap_or_default());
+        offsets.push(rows.len() as i32);
+    }
+
+    let fields = struct_fields(registry);
+    let children = registry
+        .iter()
+        .map(|field| {
+            let values: Vec<Option<&Bson>> = rows.iter().map(|row| row.get(field.name)).collect();
+            build_column(
+                &format!("{}.{}", path, field.name),
+                field.field_type,
+                &values,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let rows = StructArray::try_new(fields.clone(), children, None)?;
+
+    let item = Arc::new(ArrowField::new("item", DataType::Struct(fields), true));
+    let list = ListArray::try_new(
+        item,
+        OffsetBuffer::new(ScalarBuffer::from(offsets)),
+        Arc::new(rows),
+        Some(NullBuffer::from(valid)),
+    )?;
+    Ok(Arc::new(list))
+}
+
+/// One record batch holding `documents`, with a typed column per field.
+pub fn record_batch(
+    schema: &SchemaRef,
+    fields: &[&'static Field],
+    documents: &[Document],
+) -> Result<RecordBatch, ArrowError> {
+    let columns = fields
+        .iter()
+        .map(|field| {
+            let values: Vec<Option<&Bson>> = documents
+                .iter()
+                .map(|document| document.get(field.name))
+                .collect();
+            build_column(field.name, field.field_type, &values)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+/// Encodes record batches as a Parquet file.
+pub fn write_parquet(schema: &SchemaRef, batches: &[RecordBatch]) -> Result<Vec<u8>, ParquetError> {
+    let mut writer = ArrowWriter::try_new(Vec::new(), schema.clone(), None)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.into_inner()
+}
+
+/// Encodes record batches as an Arrow IPC stream.
+pub fn write_arrow(schema: &SchemaRef, batches: &[RecordBatch]) -> Result<Vec<u8>, ArrowError> {
+    let mut writer = StreamWriter::try_new(Vec::new(), schema)?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    writer.into_inner()
+}
+
+/// Encodes a stream of record batches as Parquet or an Arrow IPC stream,
+/// handing back the bytes of each batch as soon as they are written so a
+/// response never holds more than one batch. Parquet gets a row group per
+/// batch, written through a small buffer that may hold a batch's last few
+/// kilobytes until the next one; its footer, which lists the row groups,
+/// comes from `finish`.
+pub enum BatchEncoder {
+    Parquet(ArrowWriter<Vec<u8>>),
+    Arrow(StreamWriter<Vec<u8>>),
+}
+
+impl BatchEncoder {
+    pub fn parquet(schema: &SchemaRef) -> Result<Self, ParquetError> {
+        Ok(Self::Parquet(ArrowWriter::try_new(
+            Vec::new(),
+            schema.clone(),
+            None,
+        )?))
+    }
+
+    pub fn arrow(schema: &SchemaRef) -> Result<Self, ParquetError> {
+        Ok(Self::Arrow(StreamWriter::try_new(Vec::new(), schema)?))
+    }
+
+    /// Encodes `batch` and returns the bytes written so far.
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<Vec<u8>, ParquetError> {
+        match self {
+            Self::Parquet(writer) => {
+                writer.write(batch)?;
+                writer.flush()?;
+                Ok(std::mem::take(writer.inner_mut()))
+            }
+            Self::Arrow(writer) => {
+                writer.write(batch)?;
+                Ok(std::mem::take(writer.get_mut()))
+            }
+        }
+    }
+
+    /// Ends the file or stream and returns its remaining bytes.
+    pub fn finish(self) -> Result<Vec<u8>, ParquetError> {
+        match self {
+            Self::Parquet(writer) => writer.into_inner(),
+            Self::Arrow(mut writer) => {
+                writer.finish()?;
+                Ok(writer.into_inner()?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{depth_model::Depth, swaps_model::Swaps};
+    use actix_web::web::Bytes;
+    use bson::doc;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn export(
+        registry: FieldRegistry,
+        name: &str,
+        document: Document,
+    ) -> Result<RecordBatch, ArrowError> {
+        let selected = [name.to_string()];
+        let (schema, fields) = schema(registry, Some(&selected));
+        record_batch(&schema, &fields, &[document])
+    }
+
+    #[test]
+    fn decimals_are_read_in_whole_units() {
+        assert_eq!(decimal_units("1234"), Some(1234));
+        assert_eq!(decimal_units("1.2E+3"), Some(1200));
+        assert_eq!(decimal_units("1200E-2"), Some(12));
+        assert_eq!(decimal_units("1.5"), None);
+    }
+
+    #[test]
+    fn values_that_do_not_fit_their_column_are_errors() {
+        let error = export(
+            Depth::FIELDS,
+            "membersCount",
+            doc! { "membersCount": i64::MAX },
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("membersCount value"), "{}", error);
+
+        let fraction = Bson::Decimal128("1.5".parse().unwrap());
+        let error = export(Depth::FIELDS, "assetDepth", doc! { "assetDepth": fraction })
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("assetDepth value"), "{}", error);
+
+        let batch = export(Swaps::FIELDS, "totalCount", doc! { "totalCount": 3 }).unwrap();
+        assert_eq!(batch.num_rows(), 1);
+    }
+
+    #[test]
+    fn missing_and_null_values_are_nulls() {
+        let batch = export(
+            Swaps::FIELDS,
+            "totalCount",
+            doc! { "totalCount": Bson::Null },
+        )
+        .unwrap();
+        assert_eq!(batch.column(0).null_count(), 1);
+    }
+
+    #[test]
+    fn streamed_parquet_has_a_row_group_per_batch() {
+        let selected = ["totalCount".to_string()];
+        let (schema, fields) = schema(Swaps::FIELDS, Some(&selected));
+        let mut encoder = BatchEncoder::parquet(&schema).unwrap();
+
+        let documents: Vec<Document> = (0..5000_i64)
+            .map(|count| doc! { "totalCount": count * 7919 })
+            .collect();
+        let batch = record_batch(&schema, &fields, &documents).unwrap();
+        let mut file = Vec::new();
+        for _ in 0..3 {
+            let written = encoder.write(&batch).unwrap();
+            assert!(!written.is_empty());
+            file.extend(written);
+        }
+        file.extend(encoder.finish().unwrap());
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(file)).unwrap();
+        assert_eq!(reader.metadata().num_row_groups(), 3);
+        let rows: usize = reader
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows, 15000);
+    }
+}
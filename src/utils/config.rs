@@ -1,12 +1,28 @@
+use chrono::{DateTime, NaiveDate, Utc};
 use dotenv::dotenv;
 use std::env;
 use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::models::collection_type::CollectionType;
 
 #[derive(Debug)]
 pub struct AppConfig {
     pub mongodb_uri: String,
     pub database_name: String,
+    pub remove_duplicate_intervals: bool,
     pub server_addr: SocketAddr,
+    pub midgard_base_url: String,
+    pub midgard_pools: Vec<String>,
+    pub midgard_interval: String,
+    pub midgard_genesis: DateTime<Utc>,
+    pub backfill_pages_per_sync: u32,
+    pub depths_sync_interval: Duration,
+    pub swaps_sync_interval: Duration,
+    pub runepools_sync_interval: Duration,
+    pub earnings_sync_interval: Duration,
+    pub cache_capacity: usize,
+    pub cache_ttl: Duration,
 }
 
 #[derive(Debug)]
@@ -29,10 +45,77 @@ impl AppConfig {
                 .map_err(|_| AppError("MONGODB_URI must be set".to_string()))?,
             database_name: env::var("DATABASE_NAME")
                 .map_err(|_| AppError("DATABASE_NAME must be set".to_string()))?,
+            remove_duplicate_intervals: env::var("REMOVE_DUPLICATE_INTERVALS")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .map_err(|_| AppError("Invalid REMOVE_DUPLICATE_INTERVALS value".to_string()))?,
             server_addr: env::var("SERVER_ADDR")
                 .unwrap_or_else(|_| "0.0.0.0:3000".to_string())
                 .parse()
                 .map_err(|_| AppError("Invalid SERVER_ADDR format".to_string()))?,
+            midgard_base_url: env::var("MIDGARD_BASE_URL")
+                .unwrap_or_else(|_| "https://midgard.ninerealms.com".to_string()),
+            midgard_pools: env::var("MIDGARD_POOLS")
+                .unwrap_or_else(|_| "BTC.BTC".to_string())
+                .split(',')
+                .map(|pool| pool.trim().to_string())
+                .filter(|pool| !pool.is_empty())
+                .collect(),
+            midgard_interval: env::var("MIDGARD_INTERVAL").unwrap_or_else(|_| "hour".to_string()),
+            midgard_genesis: env::var("MIDGARD_GENESIS_DATE")
+                .unwrap_or_else(|_| "2021-04-01".to_string())
+                .parse::<NaiveDate>()
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+                .map_err(|_| AppError("Invalid MIDGARD_GENESIS_DATE format".to_string()))?,
+            backfill_pages_per_sync: env::var("BACKFILL_PAGES_PER_SYNC")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .map_err(|_| AppError("Invalid BACKFILL_PAGES_PER_SYNC value".to_string()))?,
+            depths_sync_interval: interval_from_env("DEPTHS_SYNC_INTERVAL_SECS", 3600)?,
+            swaps_sync_interval: interval_from_env("SWAPS_SYNC_INTERVAL_SECS", 3600)?,
+            runepools_sync_interval: interval_from_env("RUNEPOOLS_SYNC_INTERVAL_SECS", 3600)?,
+            earnings_sync_interval: interval_from_env("EARNINGS_SYNC_INTERVAL_SECS", 86400)?,
+            cache_capacity: env::var("CACHE_CAPACITY")
+                .unwrap_or_else(|_| "256".to_string())
+                .parse()
+                .map_err(|_| AppError("Invalid CACHE_CAPACITY value".to_string()))?,
+            cache_ttl: interval_from_env("CACHE_TTL_SECS", 600)?,
         })
     }
+
+    /// Length of one interval as stored from Midgard. Months, quarters and
+    /// years are taken at their longest.
+    pub fn midgard_interval_length(&self) -> Duration {
+        let secs = match self.midgard_interval.as_str() {
+            "5min" => 300,
+            "day" => 86_400,
+            "week" => 7 * 86_400,
+            "month" => 31 * 86_400,
+            "quarter" => 92 * 86_400,
+            "year" => 366 * 86_400,
+            _ => 3_600,
+        };
+        Duration::from_secs(secs)
+    }
+
+    pub fn sync_interval(&self, collection_type: CollectionType) -> Duration {
+        match collection_type {
+            CollectionType::Depths => self.depths_sync_interval,
+            CollectionType::Swaps => self.swaps_sync_interval,
+            CollectionType::Runepools => self.runepools_sync_interval,
+            CollectionType::Earnings => self.earnings_sync_interval,
+        }
+    }
+}
+
+fn interval_from_env(name: &str, default_secs: u64) -> Result<Duration, AppError> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .ok_or_else(|| AppError(format!("Invalid {} value", name))),
+        Err(_) => Ok(Duration::from_secs(default_secs)),
+    }
 }
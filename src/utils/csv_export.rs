@@ -0,0 +1,333 @@
+// utils/csv_export.rs
+use crate::models::field_registry::{resolve_field, FieldRegistry, FieldType};
+use serde_json::{Map, Value};
+
+pub const POOLS_LAYOUTS: [&str; 2] = ["long", "wide"];
+
+/// How rows nested in an array field, such as `Earnings.pools`, are laid out
+/// in a CSV export.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PoolsLayout {
+    /// One line per interval and pool, with the pool's fields in
+    /// `pools.<field>` columns.
+    #[default]
+    Long,
+    /// One line per interval, with each pool's fields in `<pool>.<field>`
+    /// columns.
+    Wide,
+}
+
+impl PoolsLayout {
+    pub fn parse(layout: &str) -> Option<Self> {
+        match layout {
+            "long" => Some(Self::Long),
+            "wide" => Some(Self::Wide),
+            _ => None,
+        }
+    }
+}
+
+/// Columns of a CSV export, named after the models' serde fields: the
+/// top-level fields, and the array field whose rows are flattened.
+pub struct CsvColumns {
+    fields: Vec<&'static str>,
+    rows: Option<(&'static str, Vec<&'static str>)>,
+}
+
+impl CsvColumns {
+    /// Columns for `registry`, in registry order or in the order of the
+    /// `fields=` selection when there is one.
+    pub fn new(registry: FieldRegistry, selected: Option<&[String]>) -> Self {
+        let fields: Vec<_> = match selected {
+            Some(selected) => selected
+                .iter()
+                .filter_map(|name| resolve_field(registry, name))
+                .collect(),
+            None => registry.iter().collect(),
+        };
+
+        let mut columns = Self {
+            fields: Vec::new(),
+            rows: None,
+        };
+        for field in fields {
+            match field.field_type {
+                FieldType::Array(rows) => {
+                    columns.rows = Some((field.name, rows.iter().map(|row| row.name).collect()));
+                }
+                _ => columns.fields.push(field.name),
+            }
+        }
+        columns
+    }
+
+    fn cells(&self, item: &Map<String, Value>) -> Vec<String> {
+        self.fields
+            .iter()
+            .map(|field| cell(item.get(*field)))
+            .collect()
+    }
+
+    fn nested_rows<'a>(&self, item: &'a Map<String, Value>) -> Vec<&'a Map<String, Value>> {
+        let Some((array, _)) = &self.rows else {
+            return Vec::new();
+        };
+        match item.get(*array) {
+            Some(Value::Array(rows)) => rows.iter().filter_map(Value::as_object).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Header line of the long layout.
+    pub fn long_header(&self) -> Vec<String> {
+        let mut header: Vec<String> = self.fields.iter().map(|field| field.to_string()).collect();
+        if let Some((array, row_fields)) = &self.rows {
+            header.extend(
+                row_fields
+                    .iter()
+                    .map(|field| format!("{}.{}", array, field)),
+            );
+        }
+        header
+    }
+
+    /// Lines of one item in the long layout: one per nested row, or a single
+    /// line with empty row columns when there are none.
+    pub fn long_records(&self, item: &Map<String, Value>) -> Vec<Vec<String>> {
+        let cells = self.cells(item);
+        let Some((_, row_fields)) = &self.rows else {
+            return vec![cells];
+        };
+
+        let rows = self.nested_rows(item);
+        if rows.is_empty() {
+            let mut line = cells;
+            line.resize(line.len() + row_fields.len(), String::new());
+            return vec![line];
+        }
+
+        rows.into_iter()
+            .map(|row| {
+                let mut line = cells.clone();
+                line.extend(row_fields.iter().map(|field| cell(row.get(*field))));
+                line
+            })
+            .collect()
+    }
+
+    /// Header and lines of `items` in the wide layout. Every pool seen in
+    /// `items` gets a column per row field, in the order pools first appear.
+    pub fn wide_table(&self, items: &[Map<String, Value>]) -> Vec<Vec<String>> {
+        let Some((_, row_fields)) = &self.rows else {
+            let mut table = vec![self.long_header()];
+            table.extend(items.iter().map(|item| self.cells(item)));
+            return table;
+        };
+        let row_fields: Vec<&str> = row_fields
+            .iter()
+            .copied()
+            .filter(|field| *field != "pool")
+            .collect();
+
+        let mut pools: Vec<String> = Vec::new();
+        for item in items {
+            for row in self.nested_rows(item) {
+                let pool = cell(row.get("pool"));
+                if !pools.contains(&pool) {
+                    pools.push(pool);
+                }
+            }
+        }
+
+        let mut header: Vec<String> = self.fields.iter().map(|field| field.to_string()).collect();
+        for pool in &pools {
+            header.extend(row_fields.iter().map(|field| format!("{}.{}", pool, field)));
+        }
+
+        let mut table = vec![header];
+        for item in items {
+            let rows = self.nested_rows(item);
+            let mut line = self.cells(item);
+            for pool in &pools {
+                let row = rows.iter().find(|row| cell(row.get("pool")) == *pool);
+                line.extend(
+                    row_fields
+                        .iter()
+                        .map(|field| cell(row.and_then(|row| row.get(*field)))),
+                );
+            }
+            table.push(line);
+        }
+        table
+    }
+}
+
+/// Text of one CSV cell. Values are already serialized the way the JSON
+/// responses are, so timestamps keep their millisecond string form.
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Encodes lines as CSV, quoting cells where needed.
+pub fn write_records<I>(records: I) -> Result<Vec<u8>, csv::Error>
+where
+    I: IntoIterator<Item = Vec<String>>,
+{
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    for record in records {
+        writer.write_record(&record)?;
+    }
+    writer
+        .into_inner()
+        .map_err(|error| csv::Error::from(error.into_error()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::earnings_model::Earnings;
+    use bson::{doc, DateTime as BsonDateTime, Document};
+
+    const START: i64 = 1_730_419_200_000;
+    const END: i64 = 1_730_505_600_000;
+
+    fn pool(name: &str, earnings: i64) -> Document {
+        doc! {
+            "assetLiquidityFees": 1_i64,
+            "earnings": earnings,
+            "pool": name,
+            "rewards": 2_i64,
+            "runeLiquidityFees": 3_i64,
+            "saverEarning": 4_i64,
+            "totalLiquidityFeesRune": 5_i64,
+        }
+    }
+
+    /// A stored Earnings interval, serialized the way the v1 responses are.
+    fn earnings(pools: Vec<Document>) -> Map<String, Value> {
+        let document = doc! {
+            "avgNodeCount": 98.5,
+            "blockRewards": 10_i64,
+            "bondingEarnings": 20_i64,
+            "earnings": 30_i64,
+            "endTime": BsonDateTime::from_millis(END),
+            "liquidityEarnings": 40_i64,
+            "liquidityFees": 50_i64,
+            "runePriceUSD": "4.25",
+            "startTime": BsonDateTime::from_millis(START),
+            "pools": pools,
+        };
+        let item: Earnings = bson::from_document(document).unwrap();
+        match serde_json::to_value(item).unwrap() {
+            Value::Object(object) => object,
+            other => panic!("not an object: {}", other),
+        }
+    }
+
+    fn selected(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn headers_follow_the_registry_and_timestamps_stay_in_milliseconds() {
+        let columns = CsvColumns::new(Earnings::FIELDS, None);
+        let header = columns.long_header();
+        assert_eq!(
+            header[..9],
+            [
+                "avgNodeCount",
+                "blockRewards",
+                "bondingEarnings",
+                "earnings",
+                "endTime",
+                "liquidityEarnings",
+                "liquidityFees",
+                "runePriceUSD",
+                "startTime",
+            ]
+        );
+        assert_eq!(header[9], "pools.assetLiquidityFees");
+        assert!(header.contains(&"pools.pool".to_string()));
+
+        let lines = columns.long_records(&earnings(vec![pool("BTC.BTC", 7)]));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(
+            lines[0][..9],
+            [
+                "98.5",
+                "10",
+                "20",
+                "30",
+                "1730505600000",
+                "40",
+                "50",
+                "4.25",
+                "1730419200000"
+            ]
+        );
+    }
+
+    #[test]
+    fn long_layout_has_a_line_per_pool() {
+        let selection = selected(&["startTime", "pools"]);
+        let columns = CsvColumns::new(Earnings::FIELDS, Some(&selection));
+        let item = earnings(vec![pool("BTC.BTC", 7), pool("ETH.ETH", 8)]);
+
+        let mut table = vec![columns.long_header()];
+        table.extend(columns.long_records(&item));
+        let csv = String::from_utf8(write_records(table).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "startTime,pools.assetLiquidityFees,pools.earnings,pools.pool,pools.rewards,\
+             pools.runeLiquidityFees,pools.runePriceUSD,pools.saverEarning,pools.startTime,\
+             pools.totalLiquidityFeesRune\n\
+             1730419200000,1,7,BTC.BTC,2,3,,4,,5\n\
+             1730419200000,1,8,ETH.ETH,2,3,,4,,5\n"
+        );
+
+        let empty = columns.long_records(&earnings(Vec::new()));
+        assert_eq!(empty.len(), 1);
+        assert_eq!(empty[0][0], "1730419200000");
+        assert!(empty[0][1..].iter().all(String::is_empty));
+    }
+
+    #[test]
+    fn wide_layout_has_columns_per_pool_in_first_seen_order() {
+        let selection = selected(&["startTime", "earnings", "pools"]);
+        let columns = CsvColumns::new(Earnings::FIELDS, Some(&selection));
+        let items = [
+            earnings(vec![pool("ETH.ETH", 8)]),
+            earnings(vec![pool("BTC.BTC", 7), pool("ETH.ETH", 9)]),
+        ];
+
+        let table = columns.wide_table(&items);
+        assert_eq!(table.len(), 3);
+        let header = &table[0];
+        assert_eq!(header[..2], ["startTime", "earnings"]);
+        assert_eq!(header[2], "ETH.ETH.assetLiquidityFees");
+        assert!(!header.iter().any(|column| column.ends_with(".pool")));
+
+        let column = |name: &str| header.iter().position(|column| column == name).unwrap();
+        let eth = column("ETH.ETH.earnings");
+        let btc = column("BTC.BTC.earnings");
+        assert!(eth < btc);
+        assert_eq!(table[1][eth], "8");
+        assert_eq!(table[1][btc], "");
+        assert_eq!(table[2][eth], "9");
+        assert_eq!(table[2][btc], "7");
+        assert_eq!(table[2][..2], ["1730419200000", "30"]);
+    }
+
+    #[test]
+    fn cells_with_separators_are_quoted() {
+        let lines = vec![vec!["a,b".to_string(), "say \"hi\"".to_string()]];
+        let csv = String::from_utf8(write_records(lines).unwrap()).unwrap();
+        assert_eq!(csv, "\"a,b\",\"say \"\"hi\"\"\"\n");
+    }
+}
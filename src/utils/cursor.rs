@@ -0,0 +1,183 @@
+// utils/cursor.rs
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bson::{doc, Bson, Document};
+
+/// Position just after the last document of a page, for the sort the page
+/// was read with. Encoded as base64url BSON so values keep their types.
+#[derive(Debug)]
+pub struct PageCursor {
+    /// Sort keys as returned by `sort_keys`, ending with `_id`.
+    keys: Vec<(String, i32)>,
+    /// Value of each key in the last document.
+    values: Vec<Bson>,
+}
+
+impl PageCursor {
+    pub fn after(keys: &[(String, i32)], last: &Document) -> Self {
+        Self {
+            keys: keys.to_vec(),
+            values: keys
+                .iter()
+                .map(|(field, _)| last.get(field).cloned().unwrap_or(Bson::Null))
+                .collect(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let keys: Vec<Bson> = self
+            .keys
+            .iter()
+            .map(|(field, direction)| Bson::Array(vec![field.as_str().into(), (*direction).into()]))
+            .collect();
+        let document = doc! {
+            "k": keys,
+            "v": self.values.clone(),
+        };
+        let mut bytes = Vec::new();
+        // Writing a document to a Vec cannot fail.
+        document
+            .to_writer(&mut bytes)
+            .expect("cursor serialization");
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Decodes a cursor, rejecting it unless it was issued for the same sort.
+    pub fn decode(token: &str, keys: &[(String, i32)]) -> Result<Self, String> {
+        let invalid = || "invalid cursor".to_string();
+
+        let bytes = URL_SAFE_NO_PAD.decode(token).map_err(|_| invalid())?;
+        let document = Document::from_reader(bytes.as_slice()).map_err(|_| invalid())?;
+
+        let cursor_keys = document
+            .get_array("k")
+            .map_err(|_| invalid())?
+            .iter()
+            .map(|key| match key.as_array().map(Vec::as_slice) {
+                Some([Bson::String(field), Bson::Int32(direction)]) => {
+                    Ok((field.clone(), *direction))
+                }
+                _ => Err(invalid()),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let values = document.get_array("v").map_err(|_| invalid())?.clone();
+
+        if values.len() != cursor_keys.len() {
+            return Err(invalid());
+        }
+        if cursor_keys != keys {
+            return Err("cursor was issued for a different sort_by or order".to_string());
+        }
+
+        Ok(Self {
+            keys: cursor_keys,
+            values,
+        })
+    }
+
+    /// `$match` stage keeping only documents after the cursor position: for
+    /// some key, all earlier keys equal the cursor's and that key is past it.
+    /// Null and missing values sort before every other value.
+    pub fn match_stage(&self) -> Document {
+        let branches: Vec<Document> = (0..self.keys.len())
+            .map(|i| {
+                let mut branch: Document = self.keys[..i]
+                    .iter()
+                    .zip(&self.values)
+                    .map(|((field, _), value)| (field.clone(), value.clone()))
+                    .collect();
+                let (field, direction) = &self.keys[i];
+                branch.insert(field.clone(), past(&self.values[i], *direction));
+                branch
+            })
+            .collect();
+
+        doc! { "$match": { "$or": branches } }
+    }
+}
+
+/// Condition on a sort key for values past `value` in `direction`. Range
+/// operators only compare values of the same type, so nulls are matched
+/// explicitly: they follow every value when descending, and every value
+/// follows them when ascending.
+fn past(value: &Bson, direction: i32) -> Document {
+    match (value, direction < 0) {
+        (Bson::Null, false) => doc! { "$ne": Bson::Null },
+        // Nothing sorts before null.
+        (Bson::Null, true) => doc! { "$in": [] },
+        (value, false) => doc! { "$gt": value.clone() },
+        (value, true) => doc! { "$not": { "$gte": value.clone() } },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(direction: i32) -> Vec<(String, i32)> {
+        vec![
+            ("totalVolume".to_string(), direction),
+            ("_id".to_string(), direction),
+        ]
+    }
+
+    fn branches(cursor: &PageCursor) -> Vec<Bson> {
+        cursor
+            .match_stage()
+            .get_document("$match")
+            .unwrap()
+            .get_array("$or")
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn round_trips_through_its_encoding() {
+        let last = doc! { "totalVolume": 42_i64, "_id": "abc" };
+        let cursor = PageCursor::after(&keys(1), &last);
+        let decoded = PageCursor::decode(&cursor.encode(), &keys(1)).unwrap();
+        assert_eq!(decoded.keys, keys(1));
+        assert_eq!(
+            decoded.values,
+            vec![Bson::Int64(42), Bson::String("abc".to_string())]
+        );
+    }
+
+    #[test]
+    fn rejects_cursors_for_another_sort() {
+        let cursor = PageCursor::after(&keys(1), &doc! { "totalVolume": 1, "_id": 1 });
+        let error = PageCursor::decode(&cursor.encode(), &keys(-1)).unwrap_err();
+        assert!(error.contains("different sort_by or order"));
+        assert_eq!(
+            PageCursor::decode("not a cursor", &keys(1)).unwrap_err(),
+            "invalid cursor"
+        );
+    }
+
+    #[test]
+    fn continues_past_a_null_sort_value() {
+        let last = doc! { "_id": 7 };
+        let ascending = PageCursor::after(&keys(1), &last);
+        assert_eq!(
+            branches(&ascending),
+            vec![
+                Bson::Document(doc! { "totalVolume": { "$ne": Bson::Null } }),
+                Bson::Document(doc! { "totalVolume": Bson::Null, "_id": { "$gt": 7 } }),
+            ]
+        );
+
+        let descending = PageCursor::after(&keys(-1), &last);
+        assert_eq!(
+            branches(&descending)[1],
+            Bson::Document(doc! { "totalVolume": Bson::Null, "_id": { "$not": { "$gte": 7 } } })
+        );
+    }
+
+    #[test]
+    fn descending_pages_reach_null_values() {
+        let cursor = PageCursor::after(&keys(-1), &doc! { "totalVolume": 5, "_id": 7 });
+        assert_eq!(
+            branches(&cursor)[0],
+            Bson::Document(doc! { "totalVolume": { "$not": { "$gte": 5 } } })
+        );
+    }
+}
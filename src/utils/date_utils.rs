@@ -1,38 +1,230 @@
 // utils/date_utils.rs
-use chrono::{DateTime, Utc};
-use log::warn;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
 
+/// A time range, `start` inclusive and `end` exclusive.
 #[derive(Debug)]
 pub struct DateRange {
     pub start: Option<DateTime<Utc>>,
     pub end: Option<DateTime<Utc>>,
 }
 
-pub fn parse_date_range(date_range: &str) -> Option<DateRange> {
-    let dates: Vec<&str> = date_range.split(',').collect();
-    let start_date_str = dates[0];
-    let end_date_str = dates.get(1).unwrap_or(&start_date_str);
+pub const RANGE_MODES: [&str; 2] = ["contained", "overlapping"];
 
-    let parse_date = |date: &str, is_end: bool| {
-        if date.trim().is_empty() {
-            None
-        } else {
-            let time_part = if is_end { "23:59:59" } else { "00:00:00" };
-            match DateTime::parse_from_str(
-                &format!("{} {} +0000", date, time_part),
-                "%Y-%m-%d %H:%M:%S %z",
-            ) {
-                Ok(dt) => Some(dt.with_timezone(&Utc)),
-                Err(e) => {
-                    warn!("Failed to parse date '{}': {}", date, e);
-                    None
-                }
-            }
+/// Which intervals a range selects.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RangeMode {
+    /// Intervals that lie entirely inside the range.
+    #[default]
+    Contained,
+    /// Intervals that share any time with the range.
+    Overlapping,
+}
+
+impl RangeMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "contained" => Some(Self::Contained),
+            "overlapping" => Some(Self::Overlapping),
+            _ => None,
         }
+    }
+}
+
+/// Unix timestamps from this value up are read as milliseconds, below it as
+/// seconds. In seconds it is in the year 5138, in milliseconds in 1973.
+const MILLIS_THRESHOLD: i64 = 100_000_000_000;
+
+/// Parses one end of a range: a `YYYY-MM-DD` date (the start of the day, or
+/// the start of the next day when `is_end`, so the whole day is included),
+/// unix seconds or milliseconds, or an RFC 3339 datetime with offset.
+fn parse_date(date: &str, is_end: bool) -> Result<DateTime<Utc>, String> {
+    let date = date.trim();
+
+    if let Ok(timestamp) = date.parse::<i64>() {
+        let parsed = if timestamp.abs() >= MILLIS_THRESHOLD {
+            Utc.timestamp_millis_opt(timestamp).single()
+        } else {
+            Utc.timestamp_opt(timestamp, 0).single()
+        };
+        return parsed.ok_or_else(|| format!("timestamp '{}' is out of range", date));
+    }
+
+    if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        let day = if is_end { day.succ_opt() } else { Some(day) };
+        return day
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .map(|time| Utc.from_utc_datetime(&time))
+            .ok_or_else(|| format!("date '{}' is out of range", date));
+    }
+
+    DateTime::parse_from_rfc3339(date)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(|_| {
+            format!(
+                "invalid date '{}', expected YYYY-MM-DD, unix seconds or milliseconds, or an RFC 3339 datetime",
+                date
+            )
+        })
+}
+
+/// Parses a relative range ending now: `last_<n>h`, `last_<n>d`, `last_<n>w`
+/// or `ytd` (since the start of the current UTC year).
+fn parse_relative_range(expression: &str, now: DateTime<Utc>) -> Option<DateRange> {
+    if expression == "ytd" {
+        let start = Utc.with_ymd_and_hms(now.year(), 1, 1, 0, 0, 0).single()?;
+        return Some(DateRange {
+            start: Some(start),
+            end: None,
+        });
+    }
+
+    let amount = expression.strip_prefix("last_")?;
+    let unit = amount.chars().last()?;
+    let count: i64 = amount[..amount.len() - unit.len_utf8()].parse().ok()?;
+    if count <= 0 {
+        return None;
+    }
+    let length = match unit {
+        'h' => Duration::try_hours(count)?,
+        'd' => Duration::try_days(count)?,
+        'w' => Duration::try_weeks(count)?,
+        _ => return None,
     };
 
     Some(DateRange {
-        start: parse_date(start_date_str, false),
-        end: parse_date(end_date_str, true),
+        start: now.checked_sub_signed(length),
+        end: None,
+    })
+}
+
+fn checked_range(range: DateRange) -> Result<DateRange, String> {
+    if let (Some(start), Some(end)) = (range.start, range.end) {
+        if start >= end {
+            return Err("start must be before end".to_string());
+        }
+    }
+
+    Ok(range)
+}
+
+/// Parses `date_range`: a relative expression such as `last_7d`, or one or
+/// two dates separated by a comma, either of which may be empty. A lone
+/// `YYYY-MM-DD` date is that whole day; a lone timestamp or datetime is the
+/// start of a range without an end.
+pub fn parse_date_range(date_range: &str) -> Result<DateRange, String> {
+    if let Some(range) = parse_relative_range(date_range.trim(), Utc::now()) {
+        return Ok(range);
+    }
+
+    let dates: Vec<&str> = date_range.split(',').collect();
+    if dates.len() > 2 {
+        return Err("expected at most two dates separated by a comma".to_string());
+    }
+    let start_date_str = dates[0];
+    let end_date_str = match dates.get(1) {
+        Some(end) => Some(*end),
+        None if NaiveDate::parse_from_str(start_date_str.trim(), "%Y-%m-%d").is_ok() => {
+            Some(start_date_str)
+        }
+        None => None,
+    };
+
+    parse_bounds(Some(start_date_str), end_date_str)
+}
+
+/// Parses a range given as separate `from` and `to` dates.
+pub fn parse_bounds(from: Option<&str>, to: Option<&str>) -> Result<DateRange, String> {
+    let parse = |date: Option<&str>, is_end: bool| match date.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(date) => parse_date(date, is_end).map(Some),
+    };
+
+    checked_range(DateRange {
+        start: parse(from, false)?,
+        end: parse(to, true)?,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn relative_ranges_end_now() {
+        let now = utc("2024-11-08T12:30:00Z");
+        let range = |expression| parse_relative_range(expression, now).unwrap();
+
+        assert_eq!(range("last_24h").start, Some(utc("2024-11-07T12:30:00Z")));
+        assert_eq!(range("last_7d").start, Some(utc("2024-11-01T12:30:00Z")));
+        assert_eq!(range("last_2w").start, Some(utc("2024-10-25T12:30:00Z")));
+        assert_eq!(range("ytd").start, Some(utc("2024-01-01T00:00:00Z")));
+        assert!(range("last_7d").end.is_none());
+
+        for invalid in ["last_0d", "last_7m", "last_d", "last_-1h", "7d"] {
+            assert!(parse_relative_range(invalid, now).is_none(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn timestamps_below_the_threshold_are_seconds() {
+        assert_eq!(
+            parse_date("1730419200", false),
+            Ok(utc("2024-11-01T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_date("1730419200000", false),
+            Ok(utc("2024-11-01T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_date(&(MILLIS_THRESHOLD - 1).to_string(), false),
+            Ok(utc("5138-11-16T09:46:39Z"))
+        );
+        assert_eq!(
+            parse_date(&MILLIS_THRESHOLD.to_string(), false),
+            Ok(utc("1973-03-03T09:46:40Z"))
+        );
+    }
+
+    #[test]
+    fn datetimes_are_converted_from_their_offset() {
+        assert_eq!(
+            parse_date("2024-11-01T12:00:00+02:00", false),
+            Ok(utc("2024-11-01T10:00:00Z"))
+        );
+        assert_eq!(
+            parse_date("2024-11-01T12:00:00-05:30", true),
+            Ok(utc("2024-11-01T17:30:00Z"))
+        );
+        assert!(parse_date("2024-11-01T12:00:00", false).is_err());
+    }
+
+    #[test]
+    fn a_date_end_includes_the_whole_day() {
+        let range = parse_date_range("2024-11-01,2024-11-07").unwrap();
+        assert_eq!(range.start, Some(utc("2024-11-01T00:00:00Z")));
+        assert_eq!(range.end, Some(utc("2024-11-08T00:00:00Z")));
+
+        let range = parse_date_range("2024-11-01").unwrap();
+        assert_eq!(range.end, Some(utc("2024-11-02T00:00:00Z")));
+    }
+
+    #[test]
+    fn a_lone_timestamp_or_datetime_is_an_open_start() {
+        for lone in ["1730419200", "2024-11-01T00:00:00Z"] {
+            let range = parse_date_range(lone).unwrap();
+            assert_eq!(range.start, Some(utc("2024-11-01T00:00:00Z")), "{}", lone);
+            assert!(range.end.is_none(), "{}", lone);
+        }
+    }
+
+    #[test]
+    fn rejects_inverted_ranges() {
+        assert!(parse_date_range("2024-11-08,2024-11-01").is_err());
+        assert!(parse_bounds(Some("1730419200"), Some("1730419200")).is_err());
+        assert!(parse_date_range("1,2,3").is_err());
+    }
+}
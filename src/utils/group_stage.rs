@@ -0,0 +1,339 @@
+// utils/group_stage.rs
+use crate::models::field_registry::{Aggregation, FieldRegistry, FieldType};
+use bson::{doc, Bson, Document};
+
+pub const INTERVALS: [&str; 6] = ["hour", "day", "week", "month", "quarter", "year"];
+
+/// Builds the stages grouping stored intervals into `interval` calendar
+/// buckets (UTC, weeks starting on Monday), combining each field of `fields`
+/// by its aggregation.
+/// Per-pool series are grouped separately for each pool. The group key is
+/// kept as `_id` so grouped results can be paginated like stored ones, and
+/// `startTime` and `endTime` are the bounds of the bucket.
+/// Buckets are computed with `$dateTrunc` and `$dateAdd`, so grouping needs
+/// MongoDB 5.0 or later.
+pub fn build_group_stages(
+    interval: &Option<String>,
+    fields: FieldRegistry,
+    per_pool: bool,
+) -> Vec<Document> {
+    let Some(interval) = interval.as_deref() else {
+        return Vec::new();
+    };
+    if !INTERVALS.contains(&interval) {
+        return Vec::new();
+    }
+
+    let mut bucket = doc! { "date": "$startTime", "unit": interval };
+    if interval == "week" {
+        bucket.insert("startOfWeek", "monday");
+    }
+
+    let mut key = doc! { "bucket": { "$dateTrunc": bucket } };
+    let mut group = Document::new();
+    if per_pool {
+        key.insert("pool", "$pool");
+        group.insert("pool", doc! { "$first": "$pool" });
+    }
+    group.insert("_id", key);
+
+    // Set once the group is complete: the bucket bounds, integer sums, which
+    // are added up as decimals so they are exact and brought back to the
+    // declared 64-bit type (a sum beyond its range fails the query rather
+    // than being rounded), weighted averages, which need the summed weights,
+    // and the combined pool rows.
+    let mut finish = doc! {
+        "startTime": "$_id.bucket",
+        "endTime": { "$dateAdd": { "startDate": "$_id.bucket", "unit": interval, "amount": 1 } },
+    };
+    for field in fields {
+        let Some(aggregation) = field.aggregation else {
+            continue;
+        };
+        let path = format!("${}", field.name);
+        let accumulator = match (aggregation, field.field_type) {
+            (Aggregation::Sum, FieldType::Int32 | FieldType::Int64) => {
+                finish.insert(field.name, doc! { "$toLong": &path });
+                doc! { "$sum": { "$toDecimal": path } }
+            }
+            (Aggregation::Sum, _) => doc! { "$sum": path },
+            (Aggregation::Last, _) => doc! { "$last": path },
+            (Aggregation::Avg, _) => doc! { "$avg": path },
+            (Aggregation::WeightedAvg(weight), _) => {
+                // The weight is a counter, so it is summed by the group too.
+                let weight = format!("${}", weight);
+                finish.insert(field.name, weighted_mean(&path, &weight));
+                doc! { "$sum": { "$multiply": [path, weight] } }
+            }
+            (Aggregation::PerPool, FieldType::Array(rows)) => {
+                finish.insert(field.name, combine_pool_rows(&path, rows));
+                doc! { "$push": path }
+            }
+            (Aggregation::PerPool, _) => doc! { "$last": path },
+        };
+        group.insert(field.name, accumulator);
+    }
+
+    // `$last` relies on the input being in time order.
+    vec![
+        doc! { "$sort": { "startTime": 1 } },
+        doc! { "$group": group },
+        doc! { "$set": finish },
+    ]
+}
+
+/// Expression dividing a weighted total by the sum of its weights, zero when
+/// nothing was counted. The weights may be summed as decimals, so the mean
+/// is converted back to a double.
+fn weighted_mean(total: impl Into<Bson>, weights: impl Into<Bson>) -> Document {
+    let weights = weights.into();
+    doc! {
+        "$cond": [
+            { "$eq": [weights.clone(), 0] },
+            0.0,
+            { "$toDouble": { "$divide": [total.into(), weights] } }
+        ]
+    }
+}
+
+/// Expression merging the per-interval arrays of pool rows pushed into
+/// `path` into a single row per pool.
+fn combine_pool_rows(path: &str, rows: FieldRegistry) -> Bson {
+    let mut row = doc! { "pool": "$$pool" };
+    for field in rows {
+        let Some(aggregation) = field.aggregation else {
+            continue;
+        };
+        let values = |value: Bson| {
+            doc! {
+                "$map": {
+                    "input": {
+                        "$filter": {
+                            "input": "$$rows",
+                            "as": "row",
+                            "cond": { "$eq": ["$$row.pool", "$$pool"] }
+                        }
+                    },
+                    "as": "row",
+                    "in": value
+                }
+            }
+        };
+        let value = format!("$$row.{}", field.name);
+        let combined = match (aggregation, field.field_type) {
+            (Aggregation::Sum, FieldType::Int32 | FieldType::Int64) => {
+                doc! { "$toLong": { "$sum": values(doc! { "$toDecimal": value }.into()) } }
+            }
+            (Aggregation::Sum, _) => doc! { "$sum": values(value.into()) },
+            (Aggregation::Avg, _) => doc! { "$avg": values(value.into()) },
+            (Aggregation::WeightedAvg(weight), _) => {
+                let weight = format!("$$row.{}", weight);
+                weighted_mean(
+                    doc! { "$sum": values(doc! { "$multiply": [value, &weight] }.into()) },
+                    doc! { "$sum": values(weight.into()) },
+                )
+            }
+            (Aggregation::Last | Aggregation::PerPool, _) => {
+                doc! { "$arrayElemAt": [values(value.into()), -1] }
+            }
+        };
+        row.insert(field.name, combined);
+    }
+
+    Bson::Document(doc! {
+        "$let": {
+            "vars": {
+                "rows": {
+                    "$reduce": {
+                        "input": path,
+                        "initialValue": [],
+                        "in": { "$concatArrays": ["$$value", "$$this"] }
+                    }
+                }
+            },
+            "in": {
+                "$map": {
+                    "input": { "$setUnion": ["$$rows.pool"] },
+                    "as": "pool",
+                    "in": row
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::earnings_model::Earnings;
+    use crate::models::field_registry::Field;
+
+    const FIELDS: FieldRegistry = &[
+        Field::key("startTime", "start_time", FieldType::DateTime),
+        Field::counter("count", "count", FieldType::Int32),
+        Field::counter("volumeUSD", "volume_usd", FieldType::Double),
+        Field::gauge("depth", "depth", FieldType::Decimal),
+        Field::average("nodes", "nodes", FieldType::Double),
+        Field::weighted_average("slip", "slip", FieldType::Double, "count"),
+    ];
+
+    fn stages(interval: &str, fields: FieldRegistry, per_pool: bool) -> Vec<Document> {
+        build_group_stages(&Some(interval.to_string()), fields, per_pool)
+    }
+
+    fn stage<'a>(stages: &'a [Document], name: &str) -> &'a Document {
+        stages
+            .iter()
+            .find_map(|stage| stage.get_document(name).ok())
+            .unwrap()
+    }
+
+    #[test]
+    fn only_known_intervals_are_grouped() {
+        assert!(build_group_stages(&None, FIELDS, false).is_empty());
+        assert!(stages("minute", FIELDS, false).is_empty());
+        for interval in INTERVALS {
+            assert_eq!(stages(interval, FIELDS, false).len(), 3, "{}", interval);
+        }
+    }
+
+    #[test]
+    fn buckets_are_truncated_dates_bounded_by_the_next_bucket() {
+        let day = stages("day", FIELDS, false);
+        assert_eq!(day[0], doc! { "$sort": { "startTime": 1 } });
+        assert_eq!(
+            stage(&day, "$group").get_document("_id").unwrap(),
+            &doc! { "bucket": { "$dateTrunc": { "date": "$startTime", "unit": "day" } } }
+        );
+        let set = stage(&day, "$set");
+        assert_eq!(set.get_str("startTime"), Ok("$_id.bucket"));
+        assert_eq!(
+            set.get_document("endTime").unwrap(),
+            &doc! { "$dateAdd": { "startDate": "$_id.bucket", "unit": "day", "amount": 1 } }
+        );
+        assert!(!stage(&day, "$group").contains_key("startTime"));
+
+        let week = stages("week", FIELDS, false);
+        assert_eq!(
+            stage(&week, "$group").get_document("_id").unwrap(),
+            &doc! {
+                "bucket": {
+                    "$dateTrunc": { "date": "$startTime", "unit": "week", "startOfWeek": "monday" }
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn each_aggregation_has_its_accumulator() {
+        let stages = stages("month", FIELDS, false);
+        let group = stage(&stages, "$group");
+        assert_eq!(
+            group.get_document("count").unwrap(),
+            &doc! { "$sum": { "$toDecimal": "$count" } }
+        );
+        assert_eq!(
+            group.get_document("volumeUSD").unwrap(),
+            &doc! { "$sum": "$volumeUSD" }
+        );
+        assert_eq!(
+            group.get_document("depth").unwrap(),
+            &doc! { "$last": "$depth" }
+        );
+        assert_eq!(
+            group.get_document("nodes").unwrap(),
+            &doc! { "$avg": "$nodes" }
+        );
+        assert_eq!(
+            group.get_document("slip").unwrap(),
+            &doc! { "$sum": { "$multiply": ["$slip", "$count"] } }
+        );
+
+        // Integer sums are brought back to 64-bit integers.
+        let set = stage(&stages, "$set");
+        assert_eq!(
+            set.get_document("count").unwrap(),
+            &doc! { "$toLong": "$count" }
+        );
+        assert!(!set.contains_key("volumeUSD"));
+    }
+
+    #[test]
+    fn weighted_averages_divide_by_the_summed_weight() {
+        let stages = stages("year", FIELDS, false);
+        assert_eq!(
+            stage(&stages, "$set").get_document("slip").unwrap(),
+            &doc! {
+                "$cond": [
+                    { "$eq": ["$count", 0] },
+                    0.0,
+                    { "$toDouble": { "$divide": ["$slip", "$count"] } }
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn per_pool_series_are_grouped_by_pool() {
+        let stages = stages("day", FIELDS, true);
+        let group = stage(&stages, "$group");
+        assert_eq!(
+            group.get_document("_id").unwrap().get_str("pool"),
+            Ok("$pool")
+        );
+        assert_eq!(
+            group.get_document("pool").unwrap(),
+            &doc! { "$first": "$pool" }
+        );
+    }
+
+    #[test]
+    fn earnings_pool_rows_are_combined_per_pool() {
+        let stages = stages("day", Earnings::FIELDS, false);
+        assert_eq!(
+            stage(&stages, "$group").get_document("pools").unwrap(),
+            &doc! { "$push": "$pools" }
+        );
+
+        let pools = stage(&stages, "$set")
+            .get_document("pools")
+            .unwrap()
+            .get_document("$let")
+            .unwrap();
+        let map = pools
+            .get_document("in")
+            .unwrap()
+            .get_document("$map")
+            .unwrap();
+        assert_eq!(
+            map.get_document("input").unwrap(),
+            &doc! { "$setUnion": ["$$rows.pool"] }
+        );
+
+        let row = map.get_document("in").unwrap();
+        assert_eq!(row.get_str("pool"), Ok("$$pool"));
+        let rewards = row.get_document("rewards").unwrap();
+        let sum = rewards
+            .get_document("$toLong")
+            .unwrap()
+            .get_document("$sum")
+            .unwrap()
+            .get_document("$map")
+            .unwrap();
+        assert_eq!(
+            sum.get_document("in").unwrap(),
+            &doc! { "$toDecimal": "$$row.rewards" }
+        );
+        assert_eq!(
+            sum.get_document("input")
+                .unwrap()
+                .get_document("$filter")
+                .unwrap()
+                .get_document("cond")
+                .unwrap(),
+            &doc! { "$eq": ["$$row.pool", "$$pool"] }
+        );
+        // Keys of the rows are not aggregated.
+        assert!(!row.contains_key("startTime"));
+    }
+}
@@ -1,33 +1,195 @@
 // utils/match_stage.rs
-use crate::utils::date_utils::parse_date_range;
+use crate::models::query_params::FieldFilter;
+use crate::utils::date_utils::{DateRange, RangeMode};
 use bson::{doc, Bson, DateTime as BsonDateTime, Document};
 
-pub fn build_match_stage(date_range: &Option<String>) -> Option<Document> {
-    date_range.as_ref().and_then(|date_range| {
-        let parsed_dates = parse_date_range(date_range)?;
-        let mut match_doc = doc! {};
-
-        if let Some(start) = parsed_dates.start {
-            let start_bson = BsonDateTime::from_chrono(start);
-            match_doc.insert("startTime", doc! { "$gte": start_bson });
-        }
+/// Parses a filter value as an integer, or failing that a finite double.
+pub fn filter_value(raw: &str) -> Option<Bson> {
+    if let Ok(value) = raw.parse::<i64>() {
+        return Some(Bson::Int64(value));
+    }
+    raw.parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .map(Bson::Double)
+}
 
-        if let Some(end) = parsed_dates.end {
-            let end_bson = BsonDateTime::from_chrono(end);
-            // Fixed the document access pattern
-            if let Some(existing_start) = match_doc.get("startTime").and_then(|d| d.as_document()) {
-                match_doc.insert(
-                    "startTime",
-                    doc! {
-                        "$gte": existing_start.get("$gte").unwrap_or(&Bson::Null),
-                        "$lt": end_bson
-                    },
-                );
-            } else {
-                match_doc.insert("startTime", doc! { "$lt": end_bson });
+/// Adds each `field[op]=value` filter to `match_doc`, combining filters on
+/// the same field into one condition.
+fn add_field_filters(match_doc: &mut Document, filters: &[FieldFilter]) {
+    for filter in filters {
+        let Some(value) = filter_value(&filter.value) else {
+            continue;
+        };
+        let operator = format!("${}", filter.operator);
+        match match_doc.get_document_mut(&filter.field) {
+            Ok(condition) => {
+                condition.insert(operator, value);
+            }
+            Err(_) => {
+                match_doc.insert(filter.field.clone(), doc! { operator: value });
             }
         }
+    }
+}
+
+/// Adds the conditions selecting intervals `[startTime, endTime)` that lie
+/// inside `range`, or that overlap it, to `match_doc`.
+fn add_range(match_doc: &mut Document, range: DateRange, mode: RangeMode) {
+    let start = range.start.map(BsonDateTime::from_chrono);
+    let end = range.end.map(BsonDateTime::from_chrono);
 
+    let (start_condition, end_condition) = match mode {
+        RangeMode::Contained => (
+            start.map(|start| doc! { "$gte": start }),
+            end.map(|end| doc! { "$lte": end }),
+        ),
+        RangeMode::Overlapping => (
+            end.map(|end| doc! { "$lt": end }),
+            start.map(|start| doc! { "$gt": start }),
+        ),
+    };
+
+    if let Some(condition) = start_condition {
+        match_doc.insert("startTime", condition);
+    }
+    if let Some(condition) = end_condition {
+        match_doc.insert("endTime", condition);
+    }
+}
+
+pub fn build_match_stage(
+    date_range: Option<DateRange>,
+    range_mode: RangeMode,
+    pool: Option<Bson>,
+    filters: &[FieldFilter],
+) -> Option<Document> {
+    let mut match_doc = doc! {};
+
+    if let Some(pool) = pool {
+        match_doc.insert("pool", pool);
+    }
+
+    if let Some(range) = date_range {
+        add_range(&mut match_doc, range, range_mode);
+    }
+
+    add_field_filters(&mut match_doc, filters);
+
+    if match_doc.is_empty() {
+        None
+    } else {
         Some(doc! { "$match": match_doc })
-    })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::date_utils::parse_date_range;
+    use chrono::{DateTime, TimeZone, Utc};
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 11, 1, 0, 0, 0).unwrap() + chrono::Duration::hours(hour.into())
+    }
+
+    fn range(start: Option<u32>, end: Option<u32>) -> DateRange {
+        DateRange {
+            start: start.map(at),
+            end: end.map(at),
+        }
+    }
+
+    /// Evaluates the range conditions of a `$match` stage against one
+    /// interval, the way MongoDB would.
+    fn selects(stage: &Document, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+        let conditions = stage.get_document("$match").unwrap();
+        [("startTime", start), ("endTime", end)]
+            .into_iter()
+            .all(|(field, value)| {
+                let Ok(condition) = conditions.get_document(field) else {
+                    return true;
+                };
+                let value = BsonDateTime::from_chrono(value);
+                condition.iter().all(|(op, bound)| {
+                    let bound = *bound.as_datetime().unwrap();
+                    match op.as_str() {
+                        "$gt" => value > bound,
+                        "$gte" => value >= bound,
+                        "$lt" => value < bound,
+                        "$lte" => value <= bound,
+                        other => panic!("unexpected operator {}", other),
+                    }
+                })
+            })
+    }
+
+    fn stage(range: DateRange, mode: RangeMode) -> Document {
+        build_match_stage(Some(range), mode, None, &[]).unwrap()
+    }
+
+    #[test]
+    fn contained_includes_intervals_touching_both_bounds() {
+        let stage = stage(range(Some(2), Some(5)), RangeMode::Contained);
+        assert!(selects(&stage, at(2), at(3)));
+        assert!(selects(&stage, at(4), at(5)));
+        assert!(selects(&stage, at(2), at(5)));
+    }
+
+    #[test]
+    fn contained_excludes_intervals_straddling_a_bound() {
+        let stage = stage(range(Some(2), Some(5)), RangeMode::Contained);
+        assert!(!selects(&stage, at(1), at(3)));
+        assert!(!selects(&stage, at(4), at(6)));
+        assert!(!selects(&stage, at(5), at(6)));
+        assert!(!selects(&stage, at(1), at(2)));
+    }
+
+    #[test]
+    fn overlapping_includes_intervals_straddling_a_bound() {
+        let stage = stage(range(Some(2), Some(5)), RangeMode::Overlapping);
+        assert!(selects(&stage, at(1), at(3)));
+        assert!(selects(&stage, at(4), at(6)));
+        assert!(selects(&stage, at(0), at(8)));
+        assert!(selects(&stage, at(3), at(4)));
+    }
+
+    #[test]
+    fn overlapping_excludes_intervals_only_touching_a_bound() {
+        let stage = stage(range(Some(2), Some(5)), RangeMode::Overlapping);
+        assert!(!selects(&stage, at(1), at(2)));
+        assert!(!selects(&stage, at(5), at(6)));
+    }
+
+    #[test]
+    fn open_ended_ranges_constrain_one_side_only() {
+        let from = stage(range(Some(2), None), RangeMode::Contained);
+        assert!(selects(&from, at(2), at(3)));
+        assert!(selects(&from, at(20), at(21)));
+        assert!(!selects(&from, at(1), at(3)));
+
+        let until = stage(range(None, Some(5)), RangeMode::Overlapping);
+        assert!(selects(&until, at(0), at(1)));
+        assert!(selects(&until, at(4), at(6)));
+        assert!(!selects(&until, at(5), at(6)));
+    }
+
+    #[test]
+    fn date_only_end_includes_the_last_hour_of_the_day() {
+        let day = parse_date_range("2024-11-01,2024-11-01").unwrap();
+        assert_eq!(day.start, Some(at(0)));
+        assert_eq!(day.end, Some(at(24)));
+
+        let stage = stage(day, RangeMode::Contained);
+        assert!(selects(&stage, at(0), at(1)));
+        assert!(selects(&stage, at(23), at(24)));
+        assert!(!selects(&stage, at(24), at(25)));
+    }
+
+    #[test]
+    fn empty_and_inverted_ranges_are_rejected() {
+        assert!(parse_date_range("2024-11-02,2024-11-01").is_err());
+        assert!(parse_date_range("1730419200,1730419200").is_err());
+        assert!(parse_date_range("1730419200,1730422800").is_ok());
+    }
 }
@@ -0,0 +1,35 @@
+// utils/metrics.rs
+use crate::models::collection_type::CollectionType;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Stored documents skipped because they could not be read as the model, per
+/// collection in `CollectionType::ALL` order.
+static DESERIALIZATION_FAILURES: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+pub fn record_deserialization_failures(collection_type: CollectionType, count: u64) {
+    DESERIALIZATION_FAILURES[collection_type as usize].fetch_add(count, Ordering::Relaxed);
+}
+
+/// Renders every metric in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut out = String::new();
+    out.push_str("# HELP deserialization_failures_total Stored documents skipped because they did not match the model.\n");
+    out.push_str("# TYPE deserialization_failures_total counter\n");
+    for collection_type in CollectionType::ALL {
+        let count = DESERIALIZATION_FAILURES[collection_type as usize].load(Ordering::Relaxed);
+        // Writing to a String cannot fail.
+        let _ = writeln!(
+            out,
+            "deserialization_failures_total{{collection=\"{}\"}} {}",
+            collection_type.as_str(),
+            count
+        );
+    }
+    out
+}
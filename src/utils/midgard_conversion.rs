@@ -0,0 +1,188 @@
+// utils/midgard_conversion.rs
+use crate::error::ServiceError;
+use crate::models::collection_type::CollectionType;
+use crate::models::field_registry::{resolve_field, FieldRegistry, FieldType};
+use bson::{Bson, DateTime as BsonDateTime, Decimal128, Document};
+use serde_json::{Map, Value};
+
+/// Stored type of a Midgard field. Midgard sends every number as a string,
+/// so the type comes from the model's field registry; fields the model does
+/// not declare are kept as text.
+fn field_type(fields: FieldRegistry, field: &str) -> FieldType {
+    resolve_field(fields, field).map_or(FieldType::Text, |f| f.field_type)
+}
+
+fn invalid(field: &str, value: &Value) -> ServiceError {
+    ServiceError::Upstream(format!("unexpected value for '{}': {}", field, value))
+}
+
+fn convert_value(kind: FieldType, field: &str, value: Value) -> Result<Bson, ServiceError> {
+    if value.is_null() {
+        return Ok(Bson::Null);
+    }
+
+    if let FieldType::Array(rows) = kind {
+        let Value::Array(pools) = value else {
+            return Err(invalid(field, &value));
+        };
+        return pools
+            .into_iter()
+            .map(|pool| match pool {
+                Value::Object(pool) => convert_object(pool, rows).map(Bson::Document),
+                other => Err(invalid(field, &other)),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Bson::Array);
+    }
+
+    let raw = match &value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        _ => return Err(invalid(field, &value)),
+    };
+
+    let converted = match kind {
+        FieldType::DateTime => raw
+            .parse::<i64>()
+            .ok()
+            .map(|secs| Bson::DateTime(BsonDateTime::from_millis(secs * 1000))),
+        FieldType::Decimal => raw.parse::<Decimal128>().ok().map(Bson::Decimal128),
+        FieldType::Double => raw.parse::<f64>().ok().map(Bson::Double),
+        FieldType::Int32 => raw.parse::<i32>().ok().map(Bson::Int32),
+        FieldType::Int64 => raw.parse::<i64>().ok().map(Bson::Int64),
+        FieldType::Text | FieldType::NumericText => Some(Bson::String(raw)),
+        FieldType::Array(_) => None,
+    };
+
+    converted.ok_or_else(|| invalid(field, &value))
+}
+
+fn convert_object(
+    object: Map<String, Value>,
+    fields: FieldRegistry,
+) -> Result<Document, ServiceError> {
+    let mut document = Document::new();
+    for (field, value) in object {
+        let bson = convert_value(field_type(fields, &field), &field, value)?;
+        document.insert(field, bson);
+    }
+    Ok(document)
+}
+
+/// Converts one Midgard history interval into the document shape stored in
+/// the collection, matching the types expected by the models.
+pub fn interval_to_document(
+    collection_type: CollectionType,
+    interval: Map<String, Value>,
+) -> Result<Document, ServiceError> {
+    convert_object(interval, collection_type.fields())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn convert(collection_type: CollectionType, interval: Value) -> Result<Document, ServiceError> {
+        let Value::Object(interval) = interval else {
+            panic!("not an object: {}", interval);
+        };
+        interval_to_document(collection_type, interval)
+    }
+
+    #[test]
+    fn numbers_sent_as_strings_take_the_model_types() {
+        let document = convert(
+            CollectionType::Depths,
+            json!({
+                "assetDepth": "12345678901234567890",
+                "assetPrice": "0.5",
+                "liquidityUnits": "42",
+                "membersCount": 7,
+                "startTime": "1700000000",
+                "note": "kept",
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(
+            document.get("assetDepth"),
+            Some(&Bson::Decimal128("12345678901234567890".parse().unwrap()))
+        );
+        assert_eq!(document.get("assetPrice"), Some(&Bson::Double(0.5)));
+        assert_eq!(document.get("liquidityUnits"), Some(&Bson::Int64(42)));
+        assert_eq!(document.get("membersCount"), Some(&Bson::Int32(7)));
+        assert_eq!(
+            document.get("startTime"),
+            Some(&Bson::DateTime(BsonDateTime::from_millis(
+                1_700_000_000_000
+            )))
+        );
+        // Fields the model does not declare are kept as text.
+        assert_eq!(document.get_str("note"), Ok("kept"));
+    }
+
+    #[test]
+    fn nan_is_kept_for_doubles_only() {
+        let document = convert(CollectionType::Depths, json!({ "luvi": "NaN" })).unwrap();
+        assert!(document.get_f64("luvi").unwrap().is_nan());
+
+        assert!(convert(CollectionType::Depths, json!({ "membersCount": "NaN" })).is_err());
+    }
+
+    #[test]
+    fn nulls_are_stored_as_nulls() {
+        let document = convert(
+            CollectionType::Earnings,
+            json!({ "avgNodeCount": null, "pools": null }),
+        )
+        .unwrap();
+        assert_eq!(document.get("avgNodeCount"), Some(&Bson::Null));
+        assert_eq!(document.get("pools"), Some(&Bson::Null));
+    }
+
+    #[test]
+    fn pool_rows_are_converted_by_their_registry() {
+        let document = convert(
+            CollectionType::Earnings,
+            json!({
+                "earnings": "100",
+                "runePriceUSD": "1.25",
+                "pools": [
+                    { "pool": "BTC.BTC", "earnings": "60", "runePriceUSD": "1.25" },
+                    { "pool": "ETH.ETH", "earnings": "40", "rewards": null },
+                ],
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(document.get_str("runePriceUSD"), Ok("1.25"));
+        let pools = document.get_array("pools").unwrap();
+        assert_eq!(pools.len(), 2);
+        let btc = pools[0].as_document().unwrap();
+        assert_eq!(btc.get_str("pool"), Ok("BTC.BTC"));
+        assert_eq!(btc.get("earnings"), Some(&Bson::Int64(60)));
+        assert_eq!(btc.get_str("runePriceUSD"), Ok("1.25"));
+        let eth = pools[1].as_document().unwrap();
+        assert_eq!(eth.get("rewards"), Some(&Bson::Null));
+    }
+
+    #[test]
+    fn rejects_values_that_do_not_fit_the_model() {
+        for interval in [
+            json!({ "totalCount": "many" }),
+            json!({ "totalVolume": "1.5" }),
+            json!({ "startTime": "yesterday" }),
+            json!({ "averageSlip": { "value": "1" } }),
+            json!({ "totalCount": true }),
+        ] {
+            let error = convert(CollectionType::Swaps, interval.clone()).unwrap_err();
+            assert_eq!(error.code(), "UPSTREAM_ERROR", "{}", interval);
+        }
+
+        for pools in [json!("BTC.BTC"), json!(["BTC.BTC"])] {
+            let error = convert(CollectionType::Earnings, json!({ "pools": pools })).unwrap_err();
+            assert!(error.to_string().contains("'pools'"), "{}", error);
+        }
+    }
+}
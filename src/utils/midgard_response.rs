@@ -0,0 +1,305 @@
+// utils/midgard_response.rs
+use crate::models::field_registry::{Aggregation, FieldRegistry, FieldType};
+use crate::utils::serialization_utils::{
+    document_to_json, OutputProfile, ProfileVersion, TimestampFormat,
+};
+use bson::{Bson, Decimal128, Document};
+use serde_json::{Map, Value};
+
+const MIDGARD: OutputProfile = OutputProfile {
+    version: ProfileVersion::Midgard,
+    timestamps: TimestampFormat::Seconds,
+};
+
+/// Serializes a stored interval the way Midgard does: every field of
+/// `fields` but `pool`, in alphabetical order, numbers as strings and
+/// timestamps as unix seconds.
+pub fn interval_to_json(document: Document, fields: FieldRegistry) -> Map<String, Value> {
+    let mut names: Vec<&str> = fields
+        .iter()
+        .map(|field| field.name)
+        .filter(|name| *name != "pool")
+        .collect();
+    names.sort_unstable();
+    document_to_json(document, fields, &names, MIDGARD)
+}
+
+/// Serializes a `meta` document built by `depth_meta` or `runepool_meta`,
+/// like `interval_to_json`.
+pub fn meta_to_json(document: Document) -> Map<String, Value> {
+    let mut names: Vec<String> = document.keys().cloned().collect();
+    names.sort_unstable();
+    document_to_json(document, &[], &names, MIDGARD)
+}
+
+fn number(document: &Document, field: &str) -> f64 {
+    match document.get(field) {
+        Some(Bson::Double(value)) => *value,
+        Some(Bson::Int32(value)) => f64::from(*value),
+        Some(Bson::Int64(value)) => *value as f64,
+        Some(Bson::Decimal128(value)) => value.to_string().parse().unwrap_or(0.0),
+        Some(Bson::String(value)) => value.parse().unwrap_or(0.0),
+        _ => 0.0,
+    }
+}
+
+fn ratio(end: f64, start: f64) -> f64 {
+    if start == 0.0 {
+        0.0
+    } else {
+        end / start
+    }
+}
+
+/// Value of `field` in `document`, or zero when there is no interval.
+fn value_or_zero(document: Option<&Document>, field: &str) -> Bson {
+    document
+        .and_then(|document| document.get(field))
+        .cloned()
+        .unwrap_or(Bson::Int64(0))
+}
+
+/// Midgard's depth history `meta`: the depths at the start and end of the
+/// range, taken from its first and last intervals, and how LUVI and the
+/// asset price moved between them.
+pub fn depth_meta(first: Option<&Document>, last: Option<&Document>) -> Document {
+    let mut meta = Document::new();
+    for (side, document) in [("start", first), ("end", last)] {
+        for (name, field) in [
+            ("AssetDepth", "assetDepth"),
+            ("LPUnits", "liquidityUnits"),
+            ("MemberCount", "membersCount"),
+            ("RuneDepth", "runeDepth"),
+            ("SynthUnits", "synthUnits"),
+        ] {
+            meta.insert(format!("{}{}", side, name), value_or_zero(document, field));
+        }
+    }
+    meta.insert("startTime", value_or_zero(first, "startTime"));
+    meta.insert("endTime", value_or_zero(last, "endTime"));
+
+    let (luvi_increase, price_shift_loss) = match (first, last) {
+        (Some(first), Some(last)) => {
+            let price_ratio = ratio(number(last, "assetPrice"), number(first, "assetPrice"));
+            (
+                ratio(number(last, "luvi"), number(first, "luvi")),
+                2.0 * price_ratio.sqrt() / (1.0 + price_ratio),
+            )
+        }
+        _ => (0.0, 0.0),
+    };
+    meta.insert("luviIncrease", luvi_increase);
+    meta.insert("priceShiftLoss", price_shift_loss);
+    meta
+}
+
+/// Midgard's RUNEPool history `meta`: the member count and units at the
+/// start and end of the range.
+pub fn runepool_meta(first: Option<&Document>, last: Option<&Document>) -> Document {
+    let mut meta = Document::new();
+    for (side, document) in [("start", first), ("end", last)] {
+        meta.insert(format!("{}Count", side), value_or_zero(document, "count"));
+        meta.insert(format!("{}Units", side), value_or_zero(document, "units"));
+    }
+    meta.insert("startTime", value_or_zero(first, "startTime"));
+    meta.insert("endTime", value_or_zero(last, "endTime"));
+    meta
+}
+
+/// Combines consecutive stored intervals into one, by the aggregation of
+/// each field of the registry, the way `interval=` groups them. Used for the
+/// `meta` of swaps and earnings, which is the whole range as one interval.
+pub struct Summary {
+    fields: FieldRegistry,
+    values: Document,
+    /// Weighted total and total weight of each average.
+    averages: Vec<(&'static str, f64, f64)>,
+    pools: Vec<(String, Summary)>,
+}
+
+impl Summary {
+    pub fn new(fields: FieldRegistry) -> Self {
+        Self {
+            fields,
+            values: Document::new(),
+            averages: Vec::new(),
+            pools: Vec::new(),
+        }
+    }
+
+    /// Adds the next interval in time order.
+    pub fn add(&mut self, document: &Document) {
+        for field in self.fields {
+            let value = match document.get(field.name) {
+                None | Some(Bson::Null) => continue,
+                Some(value) => value,
+            };
+            // Intervals are added in time order, so the first start time is
+            // the start of the range.
+            if field.name == "startTime" && self.values.contains_key("startTime") {
+                continue;
+            }
+            match (field.aggregation, field.field_type) {
+                (Some(Aggregation::Sum), _) => {
+                    let total = match self.values.get(field.name) {
+                        Some(total) => add(total, value),
+                        None => value.clone(),
+                    };
+                    self.values.insert(field.name, total);
+                }
+                (Some(aggregation @ (Aggregation::Avg | Aggregation::WeightedAvg(_))), _) => {
+                    let weight = match aggregation {
+                        Aggregation::WeightedAvg(weight) => number(document, weight),
+                        _ => 1.0,
+                    };
+                    let value = number(document, field.name) * weight;
+                    match self
+                        .averages
+                        .iter_mut()
+                        .find(|(name, ..)| *name == field.name)
+                    {
+                        Some((_, total, weights)) => {
+                            *total += value;
+                            *weights += weight;
+                        }
+                        None => self.averages.push((field.name, value, weight)),
+                    }
+                }
+                (Some(Aggregation::PerPool), FieldType::Array(rows)) => {
+                    let Bson::Array(values) = value else {
+                        continue;
+                    };
+                    for row in values.iter().filter_map(Bson::as_document) {
+                        let pool = row.get_str("pool").unwrap_or_default().to_string();
+                        let index = match self.pools.iter().position(|(name, _)| *name == pool) {
+                            Some(index) => index,
+                            None => {
+                                self.pools.push((pool, Summary::new(rows)));
+                                self.pools.len() - 1
+                            }
+                        };
+                        self.pools[index].1.add(row);
+                    }
+                }
+                _ => {
+                    self.values.insert(field.name, value.clone());
+                }
+            }
+        }
+    }
+
+    pub fn finish(self) -> Document {
+        let mut document = self.values;
+        for (name, total, weights) in self.averages {
+            let average = if weights == 0.0 { 0.0 } else { total / weights };
+            document.insert(name, average);
+        }
+        if let Some(field) = self
+            .fields
+            .iter()
+            .find(|field| field.aggregation == Some(Aggregation::PerPool))
+        {
+            let rows: Vec<Bson> = self
+                .pools
+                .into_iter()
+                .map(|(_, summary)| Bson::Document(summary.finish()))
+                .collect();
+            document.insert(field.name, rows);
+        }
+        document
+    }
+}
+
+/// A stored whole number, of any width.
+fn integer(value: &Bson) -> Option<i128> {
+    match value {
+        Bson::Int32(value) => Some(i128::from(*value)),
+        Bson::Int64(value) => Some(i128::from(*value)),
+        Bson::Decimal128(value) => value.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Stores a whole number as Int64, or as Decimal128 past `i64`, the way
+/// Mongo's `$sum` promotes rather than overflows.
+fn integer_to_bson(value: i128) -> Bson {
+    match i64::try_from(value) {
+        Ok(value) => Bson::Int64(value),
+        Err(_) => value
+            .to_string()
+            .parse::<Decimal128>()
+            .map_or_else(|_| Bson::String(value.to_string()), Bson::Decimal128),
+    }
+}
+
+/// Sum of two stored numbers: whole numbers stay exact, widening to
+/// Decimal128 when needed, and any double makes the sum a double.
+fn add(total: &Bson, value: &Bson) -> Bson {
+    if let (Some(a), Some(b)) = (integer(total), integer(value)) {
+        // Operands fit in Decimal128's 34 digits, so an i128 sum of two
+        // cannot overflow; past that the double is the closest there is.
+        return match a.checked_add(b) {
+            Some(sum) => integer_to_bson(sum),
+            None => Bson::Double(a as f64 + b as f64),
+        };
+    }
+    match (total, value) {
+        (Bson::Double(_), _) | (_, Bson::Double(_)) => {
+            let number = |value: &Bson| match value {
+                Bson::Double(value) => *value,
+                other => integer(other).map_or(0.0, |value| value as f64),
+            };
+            Bson::Double(number(total) + number(value))
+        }
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_past_i64_are_promoted_to_decimal() {
+        let total = add(&Bson::Int64(i64::MAX), &Bson::Int64(10));
+        assert_eq!(
+            total,
+            Bson::Decimal128("9223372036854775817".parse().unwrap())
+        );
+        assert_eq!(
+            add(&total, &Bson::Int32(3)),
+            Bson::Decimal128("9223372036854775820".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn sums_within_i64_stay_int64() {
+        assert_eq!(
+            add(&Bson::Int32(i32::MAX), &Bson::Int32(1)),
+            Bson::Int64(2_147_483_648)
+        );
+        assert_eq!(add(&Bson::Int64(2), &Bson::Double(0.5)), Bson::Double(2.5));
+    }
+
+    #[test]
+    fn slips_are_weighted_by_their_counts() {
+        let mut summary = Summary::new(crate::models::swaps_model::Swaps::FIELDS);
+        for (slip, count) in [(10.0, 1), (20.0, 3), (50.0, 0)] {
+            let mut interval = Document::new();
+            interval.insert("toAssetAverageSlip", slip);
+            interval.insert("toAssetCount", count);
+            summary.add(&interval);
+        }
+        let document = summary.finish();
+        assert_eq!(document.get_f64("toAssetAverageSlip"), Ok(17.5));
+        assert_eq!(document.get_i64("toAssetCount"), Ok(4));
+    }
+
+    #[test]
+    fn timestamps_are_unix_seconds() {
+        let mut interval = Document::new();
+        interval.insert("startTime", bson::DateTime::from_millis(1_700_000_000_000));
+        let json = meta_to_json(interval);
+        assert_eq!(json["startTime"], Value::String("1700000000".to_string()));
+    }
+}
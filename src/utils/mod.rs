@@ -1,9 +1,19 @@
+pub mod columnar_export;
 pub mod config;
+pub mod csv_export;
+pub mod cursor;
 pub mod date_utils;
+pub mod group_stage;
 pub mod match_stage;
+pub mod metrics;
+pub mod midgard_conversion;
+pub mod midgard_response;
+pub mod query_validation;
+pub mod request_id;
 pub mod serialization_utils;
 pub mod server;
 pub mod sort_stage;
 
+pub use group_stage::build_group_stages;
 pub use match_stage::build_match_stage;
-pub use sort_stage::build_sort_stage;
+pub use sort_stage::{build_sort_stage, sort_keys};
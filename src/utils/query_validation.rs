@@ -0,0 +1,330 @@
+// utils/query_validation.rs
+use crate::db::base_db::BaseDB;
+use crate::error::{InvalidParameter, ServiceError};
+use crate::models::field_registry::{resolve_field, FieldRegistry};
+use crate::models::query_params::{QueryParams, FILTER_OPERATORS, FORMATS};
+use crate::utils::csv_export::{PoolsLayout, POOLS_LAYOUTS};
+use crate::utils::date_utils::{parse_bounds, parse_date_range, RangeMode, RANGE_MODES};
+use crate::utils::group_stage::INTERVALS;
+use crate::utils::match_stage::filter_value;
+use crate::utils::serialization_utils::{ProfileVersion, TimestampFormat, PROFILES, TS_FORMATS};
+
+/// Checks every query parameter up front and reports all invalid ones at
+/// once. `fields` is the registry of the returned model and `per_pool`
+/// whether `pool` is accepted. Field names may be given in snake_case or
+/// camelCase and are rewritten to the stored name.
+pub fn validate_query(
+    params: &mut QueryParams,
+    fields: FieldRegistry,
+    per_pool: bool,
+) -> Result<(), ServiceError> {
+    let mut invalid = Vec::new();
+    let mut reject = |param: &str, message: String| {
+        invalid.push(InvalidParameter {
+            param: param.to_string(),
+            message,
+        })
+    };
+
+    if let Some(date_range) = &params.date_range {
+        if let Err(message) = parse_date_range(date_range) {
+            reject("date_range", message);
+        }
+    }
+
+    let from = params.from.as_deref();
+    let to = params.to.as_deref();
+    if params.date_range.is_some() && (from.is_some() || to.is_some()) {
+        let param = if from.is_some() { "from" } else { "to" };
+        reject(param, "cannot be combined with date_range".to_string());
+    } else {
+        let from_error = parse_bounds(from, None).err();
+        let to_error = parse_bounds(None, to).err();
+        let both_valid = from_error.is_none() && to_error.is_none();
+        if let Some(message) = from_error {
+            reject("from", message);
+        }
+        if let Some(message) = to_error {
+            reject("to", message);
+        }
+        if let (true, Err(message)) = (both_valid, parse_bounds(from, to)) {
+            reject("to", message);
+        }
+    }
+
+    if let Some(mode) = &params.range_mode {
+        if RangeMode::parse(mode).is_none() {
+            reject(
+                "range_mode",
+                format!("'{}' must be one of {}", mode, RANGE_MODES.join(", ")),
+            );
+        }
+    }
+
+    if let Some(order) = &params.order {
+        if order != "asc" && order != "desc" {
+            reject("order", format!("'{}' must be 'asc' or 'desc'", order));
+        }
+    }
+
+    // Streams are not paged, so their `limit` is not capped.
+    if let Some(limit) = params.limit {
+        if params.is_streaming() && limit < 1 {
+            reject("limit", format!("{} must be at least 1", limit));
+        } else if !params.is_streaming() && !(1..=BaseDB::MAX_RECORDS_NO_FILTER).contains(&limit) {
+            reject(
+                "limit",
+                format!(
+                    "{} must be between 1 and {}",
+                    limit,
+                    BaseDB::MAX_RECORDS_NO_FILTER
+                ),
+            );
+        }
+    }
+
+    if let Some(format) = &params.format {
+        if !FORMATS.contains(&format.as_str()) {
+            reject(
+                "format",
+                format!("'{}' must be one of {}", format, FORMATS.join(", ")),
+            );
+        }
+    }
+
+    if let Some(layout) = &params.pools_layout {
+        if PoolsLayout::parse(layout).is_none() {
+            reject(
+                "pools_layout",
+                format!("'{}' must be one of {}", layout, POOLS_LAYOUTS.join(", ")),
+            );
+        } else if params.is_streaming() && params.pools_layout() == PoolsLayout::Wide {
+            reject(
+                "pools_layout",
+                "'wide' needs every pool up front and cannot be streamed".to_string(),
+            );
+        }
+    }
+
+    if let Some(profile) = &params.profile {
+        if ProfileVersion::parse(profile).is_none() {
+            reject(
+                "profile",
+                format!("'{}' must be one of {}", profile, PROFILES.join(", ")),
+            );
+        }
+    }
+
+    if let Some(ts_format) = &params.ts_format {
+        if TimestampFormat::parse(ts_format).is_none() {
+            reject(
+                "ts_format",
+                format!("'{}' must be one of {}", ts_format, TS_FORMATS.join(", ")),
+            );
+        }
+    }
+
+    if let Some(sort_by) = params.sort_by.as_mut() {
+        let mut resolved = Vec::new();
+        let mut seen = Vec::new();
+        for entry in sort_by.split(',').map(str::trim) {
+            let (prefix, name) = match entry.strip_prefix('-') {
+                Some(name) => ("-", name),
+                None => ("", entry),
+            };
+            match resolve_field(fields, name).filter(|field| field.is_sortable()) {
+                Some(field) if seen.contains(&field.name) => {
+                    reject(
+                        "sort_by",
+                        format!("field '{}' is listed more than once", name),
+                    );
+                }
+                Some(field) => {
+                    seen.push(field.name);
+                    resolved.push(format!("{}{}", prefix, field.name));
+                }
+                None => {
+                    let sortable: Vec<&str> = fields
+                        .iter()
+                        .filter(|field| field.is_sortable())
+                        .map(|field| field.name)
+                        .collect();
+                    reject(
+                        "sort_by",
+                        format!(
+                            "unknown field '{}', expected one of {}",
+                            name,
+                            sortable.join(", ")
+                        ),
+                    );
+                }
+            }
+        }
+        *sort_by = resolved.join(",");
+    }
+
+    if let Some(selected) = params.selected_fields() {
+        let mut resolved = Vec::new();
+        for name in selected {
+            match resolve_field(fields, name) {
+                Some(field) if !resolved.contains(&field.name) => resolved.push(field.name),
+                Some(_) => {}
+                None => reject("fields", format!("unknown field '{}'", name)),
+            }
+        }
+        if resolved.is_empty() {
+            reject("fields", "at least one field is required".to_string());
+        }
+        params.fields = Some(resolved.join(","));
+    }
+
+    if let Some(interval) = &params.interval {
+        if !INTERVALS.contains(&interval.as_str()) {
+            reject(
+                "interval",
+                format!("'{}' must be one of {}", interval, INTERVALS.join(", ")),
+            );
+        }
+    }
+
+    for filter in params.filters.iter_mut() {
+        let param = filter.param();
+        match resolve_field(fields, &filter.field) {
+            Some(field) if field.field_type.is_numeric() => {
+                filter.field = field.name.to_string();
+            }
+            Some(_) => reject(&param, "only numeric fields can be filtered".to_string()),
+            None => reject(&param, format!("unknown field '{}'", filter.field)),
+        }
+        if !FILTER_OPERATORS.contains(&filter.operator.as_str()) {
+            reject(
+                &param,
+                format!(
+                    "'{}' must be one of {}",
+                    filter.operator,
+                    FILTER_OPERATORS.join(", ")
+                ),
+            );
+        }
+        if filter_value(&filter.value).is_none() {
+            reject(&param, format!("'{}' is not a number", filter.value));
+        }
+    }
+
+    if params.pool.is_some() && !per_pool {
+        reject("pool", "not supported for this collection".to_string());
+    }
+
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(ServiceError::InvalidParameters(invalid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ApiError;
+    use crate::models::query_params::FieldFilter;
+    use crate::models::swaps_model::Swaps;
+    use actix_web::{body::to_bytes, ResponseError};
+    use serde_json::Value;
+
+    fn rejected(mut params: QueryParams) -> Vec<String> {
+        match validate_query(&mut params, Swaps::FIELDS, true) {
+            Ok(()) => Vec::new(),
+            Err(ServiceError::InvalidParameters(invalid)) => {
+                invalid.into_iter().map(|invalid| invalid.param).collect()
+            }
+            Err(error) => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[actix_web::test]
+    async fn reports_every_invalid_parameter_together() {
+        let mut params = QueryParams {
+            order: Some("up".to_string()),
+            limit: Some(0),
+            sort_by: Some("nope".to_string()),
+            interval: Some("minute".to_string()),
+            filters: vec![FieldFilter {
+                field: "totalVolume".to_string(),
+                operator: "above".to_string(),
+                value: "1".to_string(),
+            }],
+            ..Default::default()
+        };
+        let error = ApiError {
+            request_id: "test".to_string(),
+            error: validate_query(&mut params, Swaps::FIELDS, true).unwrap_err(),
+        };
+
+        let body = to_bytes(error.error_response().into_body()).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let details: Vec<&str> = body["error"]["details"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|detail| detail["param"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            details,
+            [
+                "order",
+                "limit",
+                "sort_by",
+                "interval",
+                "totalVolume[above]"
+            ]
+        );
+        assert_eq!(body["error"]["code"], "VALIDATION_ERROR");
+    }
+
+    #[test]
+    fn pages_cap_limit_but_streams_do_not() {
+        let page = |limit| QueryParams {
+            limit: Some(limit),
+            ..Default::default()
+        };
+        let stream = |limit| QueryParams {
+            limit: Some(limit),
+            stream: Some(true),
+            ..Default::default()
+        };
+
+        assert!(rejected(page(BaseDB::MAX_RECORDS_NO_FILTER)).is_empty());
+        assert_eq!(rejected(page(BaseDB::MAX_RECORDS_NO_FILTER + 1)), ["limit"]);
+        assert_eq!(rejected(page(0)), ["limit"]);
+
+        assert!(rejected(stream(BaseDB::MAX_RECORDS_NO_FILTER + 1)).is_empty());
+        assert!(rejected(stream(1_000_000)).is_empty());
+        assert_eq!(rejected(stream(0)), ["limit"]);
+
+        let ndjson = QueryParams {
+            limit: Some(5_000),
+            format: Some("ndjson".to_string()),
+            ..Default::default()
+        };
+        assert!(rejected(ndjson).is_empty());
+    }
+
+    #[test]
+    fn field_names_are_rewritten_to_stored_names() {
+        let mut params = QueryParams {
+            sort_by: Some("-total_volume, start_time".to_string()),
+            fields: Some("total_volume_usd,startTime,totalVolumeUSD".to_string()),
+            filters: vec![FieldFilter {
+                field: "to_asset_count".to_string(),
+                operator: "gte".to_string(),
+                value: "10".to_string(),
+            }],
+            ..Default::default()
+        };
+        validate_query(&mut params, Swaps::FIELDS, true).unwrap();
+
+        assert_eq!(params.sort_by.as_deref(), Some("-totalVolume,startTime"));
+        assert_eq!(params.fields.as_deref(), Some("totalVolumeUSD,startTime"));
+        assert_eq!(params.filters[0].field, "toAssetCount");
+    }
+}
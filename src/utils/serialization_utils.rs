@@ -1,5 +1,8 @@
-use bson::{DateTime as BsonDateTime, Decimal128};
+use crate::models::field_registry::{resolve_field, FieldRegistry, FieldType};
+use bson::{Bson, DateTime as BsonDateTime, Decimal128, Document};
+use chrono::SecondsFormat;
 use serde::Serializer;
+use serde_json::{Map, Number, Value};
 
 pub fn serialize_datetime_as_timestamp<S>(
     datetime: &BsonDateTime,
@@ -20,3 +23,230 @@ where
 {
     serializer.serialize_str(&decimal.to_string())
 }
+
+pub const PROFILES: [&str; 2] = ["v1", "v2"];
+
+pub const TS_FORMATS: [&str; 2] = ["ms", "iso"];
+
+/// Version of the JSON output.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ProfileVersion {
+    /// Timestamps and decimals as strings, and numbers Midgard sends as text
+    /// kept as text, as the models serialize them.
+    #[default]
+    V1,
+    /// Every number as a JSON number: timestamps, decimals written with all
+    /// their digits, and prices stored as text.
+    V2,
+    /// Midgard's own output: every number as a string and timestamps as
+    /// unix seconds. Used by the Midgard-compatible routes rather than
+    /// selected with `profile=`.
+    Midgard,
+}
+
+impl ProfileVersion {
+    pub fn parse(version: &str) -> Option<Self> {
+        match version {
+            "v1" => Some(Self::V1),
+            "v2" => Some(Self::V2),
+            _ => None,
+        }
+    }
+}
+
+/// How timestamps are written.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TimestampFormat {
+    /// Unix milliseconds; a number in `V2` and a string otherwise.
+    #[default]
+    Millis,
+    /// ISO 8601 in UTC, e.g. `2024-01-01T00:00:00Z`.
+    Iso,
+    /// Unix seconds, as Midgard writes them; not selectable with
+    /// `ts_format=`.
+    Seconds,
+}
+
+impl TimestampFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "ms" => Some(Self::Millis),
+            "iso" => Some(Self::Iso),
+            _ => None,
+        }
+    }
+}
+
+/// Shape of JSON output, chosen with `profile=` and `ts_format=`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OutputProfile {
+    pub version: ProfileVersion,
+    pub timestamps: TimestampFormat,
+}
+
+impl OutputProfile {
+    /// Whether the output is the models' own serialization.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn timestamp(&self, datetime: BsonDateTime) -> Value {
+        match (self.timestamps, self.version) {
+            (TimestampFormat::Iso, _) => Value::String(
+                datetime
+                    .to_chrono()
+                    .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            ),
+            (TimestampFormat::Millis | TimestampFormat::Seconds, version) => {
+                let value = match self.timestamps {
+                    TimestampFormat::Seconds => datetime.timestamp_millis() / 1000,
+                    _ => datetime.timestamp_millis(),
+                };
+                match version {
+                    ProfileVersion::V2 => Value::from(value),
+                    ProfileVersion::V1 | ProfileVersion::Midgard => {
+                        Value::String(value.to_string())
+                    }
+                }
+            }
+        }
+    }
+
+    /// A number stored as text: a JSON number in `V2`, with every digit
+    /// kept, and the text itself otherwise or when it is not a number.
+    fn number(&self, text: String) -> Value {
+        match self.version {
+            ProfileVersion::V1 | ProfileVersion::Midgard => Value::String(text),
+            ProfileVersion::V2 => match text.parse::<Number>() {
+                Ok(number) => Value::Number(number),
+                Err(_) => Value::String(text),
+            },
+        }
+    }
+}
+
+/// Converts a stored value to JSON in the given profile, arrays of rows by
+/// their own registry. Non-finite doubles are written as null.
+fn value_to_json(value: Bson, field_type: FieldType, profile: OutputProfile) -> Value {
+    match (value, field_type) {
+        (Bson::DateTime(datetime), _) => profile.timestamp(datetime),
+        (Bson::Decimal128(decimal), _) => profile.number(decimal.to_string()),
+        (Bson::String(text), FieldType::NumericText) => profile.number(text),
+        (Bson::Double(value), _) if profile.version == ProfileVersion::Midgard => {
+            Value::String(value.to_string())
+        }
+        // JSON has no NaN or infinities; relaxed extended JSON would write
+        // them as `{"$numberDouble": "NaN"}` objects.
+        (Bson::Double(value), _) if !value.is_finite() => Value::Null,
+        (Bson::Int32(value), _) if profile.version == ProfileVersion::Midgard => {
+            Value::String(value.to_string())
+        }
+        (Bson::Int64(value), _) if profile.version == ProfileVersion::Midgard => {
+            Value::String(value.to_string())
+        }
+        (Bson::Array(rows), FieldType::Array(row_fields)) => Value::Array(
+            rows.into_iter()
+                .map(|row| match row {
+                    Bson::Document(row) => {
+                        let names: Vec<&str> = row_fields.iter().map(|field| field.name).collect();
+                        Value::Object(document_to_json(row, row_fields, &names, profile))
+                    }
+                    other => other.into_relaxed_extjson(),
+                })
+                .collect(),
+        ),
+        (value, _) => value.into_relaxed_extjson(),
+    }
+}
+
+/// Serializes the `selected` fields of a stored document, typed by
+/// `fields`. Missing and null fields are left out.
+pub fn document_to_json<S: AsRef<str>>(
+    mut document: Document,
+    fields: FieldRegistry,
+    selected: &[S],
+    profile: OutputProfile,
+) -> Map<String, Value> {
+    let mut object = Map::new();
+    for name in selected {
+        let name = name.as_ref();
+        let field_type = resolve_field(fields, name).map_or(FieldType::Text, |f| f.field_type);
+        match document.remove(name) {
+            None | Some(Bson::Null) => {}
+            Some(value) => {
+                object.insert(name.to_string(), value_to_json(value, field_type, profile));
+            }
+        }
+    }
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::earnings_model::Earnings;
+    use bson::doc;
+
+    const V2: OutputProfile = OutputProfile {
+        version: ProfileVersion::V2,
+        timestamps: TimestampFormat::Millis,
+    };
+
+    fn to_json(document: Document, profile: OutputProfile) -> Value {
+        let names: Vec<String> = document.keys().cloned().collect();
+        Value::Object(document_to_json(
+            document,
+            Earnings::FIELDS,
+            &names,
+            profile,
+        ))
+    }
+
+    #[test]
+    fn v2_writes_numbers_as_numbers() {
+        let document = doc! {
+            "startTime": BsonDateTime::from_millis(1_704_067_200_000),
+            "avgNodeCount": 98.5,
+            "earnings": 12_345_678_901_i64,
+            "runePriceUSD": "4.2500000001",
+        };
+        assert_eq!(
+            to_json(document, V2).to_string(),
+            r#"{"startTime":1704067200000,"avgNodeCount":98.5,"earnings":12345678901,"runePriceUSD":4.2500000001}"#
+        );
+    }
+
+    #[test]
+    fn non_finite_doubles_are_null() {
+        let document = doc! {
+            "avgNodeCount": f64::NAN,
+            "pools": [{ "pool": "BTC.BTC", "earnings": 1_i64 }],
+        };
+        let json = to_json(document, V2);
+        assert_eq!(json["avgNodeCount"], Value::Null);
+        assert_eq!(json["pools"][0]["earnings"], Value::from(1));
+
+        for value in [f64::INFINITY, f64::NEG_INFINITY] {
+            let json = to_json(doc! { "avgNodeCount": value }, OutputProfile::default());
+            assert_eq!(json, serde_json::json!({ "avgNodeCount": null }));
+        }
+    }
+
+    #[test]
+    fn iso_timestamps_are_utc_in_either_profile() {
+        for version in [ProfileVersion::V1, ProfileVersion::V2] {
+            let profile = OutputProfile {
+                version,
+                timestamps: TimestampFormat::Iso,
+            };
+            assert_eq!(
+                profile.timestamp(BsonDateTime::from_millis(1_704_067_200_000)),
+                Value::from("2024-01-01T00:00:00Z")
+            );
+            assert_eq!(
+                profile.timestamp(BsonDateTime::from_millis(1_704_067_200_250)),
+                Value::from("2024-01-01T00:00:00.250Z")
+            );
+        }
+    }
+}
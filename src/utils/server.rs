@@ -1,5 +1,7 @@
+use crate::handlers::error_handler::{not_found, query_error_handler};
+use crate::utils::request_id::assign_request_id;
 use crate::{routes, state::AppState, utils::config::AppConfig};
-use actix_web::{App, HttpServer};
+use actix_web::{middleware::from_fn, web, App, HttpServer};
 
 pub async fn setup_app(
     config: AppConfig,
@@ -8,7 +10,10 @@ pub async fn setup_app(
     let server = HttpServer::new(move || {
         App::new()
             .app_data(state.data_service.clone())
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
             .configure(routes::config)
+            .default_service(web::to(not_found))
+            .wrap(from_fn(assign_request_id))
             .wrap(actix_web::middleware::Logger::default())
             .wrap(actix_web::middleware::Compress::default())
     })
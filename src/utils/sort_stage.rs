@@ -1,19 +1,80 @@
 // utils/sort_stage.rs
 use bson::{doc, Document};
 
-pub fn build_sort_stage(sort_by: &Option<String>, order: &Option<String>) -> Option<Document> {
-    sort_by.as_ref().map(|sort_by| {
-        let order_value = if order.as_deref() == Some("desc") {
-            -1
-        } else {
-            1
-        };
-        let sort_field = if sort_by == "startTime" {
-            "startTime"
-        } else {
-            sort_by
-        };
-
-        doc! { "$sort": { sort_field: order_value } }
-    })
+/// Fields and directions (1 or -1) results are ordered by, in priority
+/// order and ending with `_id` so the order is total. `sort_by` is a
+/// comma-separated list of fields, each descending when prefixed with `-`
+/// and otherwise in `order`; without it results are ordered by `startTime`.
+pub fn sort_keys(sort_by: &Option<String>, order: &Option<String>) -> Vec<(String, i32)> {
+    let direction = if order.as_deref() == Some("desc") {
+        -1
+    } else {
+        1
+    };
+
+    let mut keys: Vec<(String, i32)> = sort_by
+        .as_deref()
+        .unwrap_or("startTime")
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| match field.strip_prefix('-') {
+            Some(field) => (field.to_string(), -1),
+            None => (field.to_string(), direction),
+        })
+        .collect();
+    keys.push(("_id".to_string(), direction));
+
+    keys
+}
+
+/// Sorts by the requested keys, breaking ties on `_id` so pages are stable.
+pub fn build_sort_stage(sort_by: &Option<String>, order: &Option<String>) -> Document {
+    let sort: Document = sort_keys(sort_by, order)
+        .into_iter()
+        .map(|(field, direction)| (field, direction.into()))
+        .collect();
+    doc! { "$sort": sort }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn results_default_to_start_time_with_an_id_tiebreak() {
+        assert_eq!(
+            build_sort_stage(&None, &None),
+            doc! { "$sort": { "startTime": 1, "_id": 1 } }
+        );
+        assert_eq!(
+            build_sort_stage(&None, &text("desc")),
+            doc! { "$sort": { "startTime": -1, "_id": -1 } }
+        );
+    }
+
+    #[test]
+    fn prefixed_fields_sort_descending_in_a_compound_sort() {
+        assert_eq!(
+            sort_keys(&text("-totalVolume, startTime"), &None),
+            [
+                ("totalVolume".to_string(), -1),
+                ("startTime".to_string(), 1),
+                ("_id".to_string(), 1),
+            ]
+        );
+
+        // The tiebreak follows `order`, as do fields without a prefix.
+        let stage = build_sort_stage(&text("-totalVolume,totalCount"), &text("desc"));
+        assert_eq!(
+            stage,
+            doc! { "$sort": { "totalVolume": -1, "totalCount": -1, "_id": -1 } }
+        );
+        let keys: Vec<&String> = stage.get_document("$sort").unwrap().keys().collect();
+        assert_eq!(keys, ["totalVolume", "totalCount", "_id"]);
+    }
 }